@@ -1,13 +1,33 @@
+use image::ImageEncoder;
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use sha2::{Digest, Sha256};
+use std::fmt::Write as _;
 use std::fs;
+use std::io::Write as _;
 use std::path::{Path, PathBuf};
 
+// Target widths for responsive hero images; the original width is always kept too
+const RESPONSIVE_WIDTHS: &[u32] = &[480, 800, 1200];
+const JPEG_QUALITY: u8 = 80;
+const AVIF_QUALITY: u8 = 60;
+// Fastest AVIF setting; slower ones shave a few percent off for several times the build time
+const AVIF_SPEED: u8 = 10;
+// PDF thumbnails are drawn at twice the 320px they're shown at
+const THUMBNAIL_WIDTH: f32 = 640.0;
+// Text assets worth shipping as `.gz`/`.br` siblings; media is already compressed
+const COMPRESSIBLE_EXTENSIONS: &[&str] = &["css", "js", "svg", "html", "json", "txt", "vtt", "xml"];
+// Hex digits of the content hash embedded in fingerprinted file names
+const FINGERPRINT_LEN: usize = 8;
+
 fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> std::io::Result<()> {
     fs::create_dir_all(&dst)?;
     for entry in fs::read_dir(src)? {
         let entry = entry?;
         let ty = entry.file_type()?;
         if ty.is_dir() {
-            copy_dir_all(&entry.path(), &dst.as_ref().join(entry.file_name()))?;
+            copy_dir_all(entry.path(), dst.as_ref().join(entry.file_name()))?;
         } else {
             fs::copy(entry.path(), dst.as_ref().join(entry.file_name()))?;
         }
@@ -15,6 +35,486 @@ fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> std::io::Result
     Ok(())
 }
 
+struct Variant {
+    url: String,
+    width: u32,
+    format: &'static str,
+}
+
+struct ResponsiveImage {
+    src: String,
+    width: u32,
+    height: u32,
+    variants: Vec<Variant>,
+}
+
+fn variant_stem(file_stem: &str) -> String {
+    file_stem
+        .chars()
+        .map(|ch| {
+            if ch.is_ascii_alphanumeric() {
+                ch.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+// Whether `output` was written after both `source` and this build script
+fn is_up_to_date(output: &Path, source: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    let inputs = [
+        modified(source),
+        std::env::current_exe().ok().and_then(|exe| modified(&exe)),
+    ];
+    match modified(output) {
+        Some(output) => inputs
+            .iter()
+            .all(|input| input.is_some_and(|input| input <= output)),
+        None => false,
+    }
+}
+
+// Resize every hero image in `static/media/images` to a few widths and write
+// JPEG and AVIF copies next to the copied static files.
+fn generate_responsive_images(images_src: &Path, static_dst: &Path) -> Vec<ResponsiveImage> {
+    let variants_dst = static_dst.join("media/images/responsive");
+    fs::create_dir_all(&variants_dst).expect("Failed to create responsive image directory");
+
+    let mut entries: Vec<PathBuf> = fs::read_dir(images_src)
+        .expect("Failed to read image directory")
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            matches!(
+                path.extension().and_then(|ext| ext.to_str()),
+                Some("jpg" | "jpeg" | "png")
+            )
+        })
+        .collect();
+    entries.sort();
+
+    let mut manifest = Vec::new();
+    for path in entries {
+        let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+        let stem = variant_stem(&path.file_stem().unwrap().to_string_lossy());
+        let original = image::open(&path)
+            .unwrap_or_else(|e| panic!("Failed to decode {}: {}", path.display(), e));
+        let (width, height) = (original.width(), original.height());
+
+        let mut widths: Vec<u32> = RESPONSIVE_WIDTHS
+            .iter()
+            .copied()
+            .filter(|w| *w < width)
+            .collect();
+        widths.push(width);
+
+        let mut variants = Vec::new();
+        for target in widths {
+            let resized = if target == width {
+                original.to_rgb8()
+            } else {
+                let target_height = (height as u64 * target as u64 / width as u64) as u32;
+                original
+                    .resize_exact(target, target_height, FilterType::Lanczos3)
+                    .to_rgb8()
+            };
+
+            // Re-encoding the full-size JPEG only makes it bigger, so point at the original
+            let jpeg_url = if target == width {
+                format!("/static/media/images/{}", file_name)
+            } else {
+                let jpeg_name = format!("{}-{}.jpg", stem, target);
+                let mut jpeg = Vec::new();
+                JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY)
+                    .encode_image(&resized)
+                    .expect("Failed to encode JPEG variant");
+                fs::write(variants_dst.join(&jpeg_name), jpeg)
+                    .expect("Failed to write JPEG variant");
+                format!("/static/media/images/responsive/{}", jpeg_name)
+            };
+            variants.push(Variant {
+                url: jpeg_url,
+                width: target,
+                format: "jpeg",
+            });
+
+            // AVIF encoding dominates the build, so keep variants from the last run
+            let avif_name = format!("{}-{}.avif", stem, target);
+            let avif_path = variants_dst.join(&avif_name);
+            if !is_up_to_date(&avif_path, &path) {
+                let mut avif = Vec::new();
+                AvifEncoder::new_with_speed_quality(&mut avif, AVIF_SPEED, AVIF_QUALITY)
+                    .write_image(
+                        &resized,
+                        resized.width(),
+                        resized.height(),
+                        image::ExtendedColorType::Rgb8,
+                    )
+                    .expect("Failed to encode AVIF variant");
+                fs::write(&avif_path, avif).expect("Failed to write AVIF variant");
+            }
+            variants.push(Variant {
+                url: format!("/static/media/images/responsive/{}", avif_name),
+                width: target,
+                format: "avif",
+            });
+        }
+
+        manifest.push(ResponsiveImage {
+            src: format!("/static/media/images/{}", file_name),
+            width,
+            height,
+            variants,
+        });
+    }
+
+    manifest
+}
+
+fn write_image_manifest(out_dir: &Path, images: &[ResponsiveImage]) {
+    let mut code = String::from("pub static IMAGE_MANIFEST: &[ResponsiveImage] = &[\n");
+    for image in images {
+        writeln!(
+            code,
+            "    ResponsiveImage {{ src: {:?}, width: {}, height: {}, variants: &[",
+            image.src, image.width, image.height
+        )
+        .unwrap();
+        for variant in &image.variants {
+            writeln!(
+                code,
+                "        ImageVariant {{ url: {:?}, width: {}, format: {:?} }},",
+                variant.url, variant.width, variant.format
+            )
+            .unwrap();
+        }
+        code.push_str("    ] },\n");
+    }
+    code.push_str("];\n");
+    fs::write(out_dir.join("image_manifest.rs"), code).expect("Failed to write image manifest");
+}
+
+struct PdfThumbnail {
+    attachment: String,
+    url: String,
+    width: u32,
+    height: u32,
+}
+
+// Render the first page of every PDF at the top of `static/` (where the
+// attachments live) to a JPEG under `thumbnails/`. A PDF that can't be
+// rendered only costs its thumbnail.
+fn generate_pdf_thumbnails(static_src: &Path, static_dst: &Path) -> Vec<PdfThumbnail> {
+    let thumbnails_dst = static_dst.join("thumbnails");
+    fs::create_dir_all(&thumbnails_dst).expect("Failed to create thumbnail directory");
+
+    let mut pdfs: Vec<PathBuf> = fs::read_dir(static_src)
+        .expect("Failed to read static directory")
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("pdf"))
+        .collect();
+    pdfs.sort();
+
+    let mut thumbnails = Vec::new();
+    for path in pdfs {
+        let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+        let Some(thumbnail) = render_first_page(&path) else {
+            println!(
+                "cargo:warning=Could not render a thumbnail for {}",
+                file_name
+            );
+            continue;
+        };
+
+        let thumbnail_name = format!(
+            "{}.jpg",
+            variant_stem(&path.file_stem().unwrap().to_string_lossy())
+        );
+        let mut jpeg = Vec::new();
+        JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY)
+            .encode_image(&thumbnail)
+            .expect("Failed to encode PDF thumbnail");
+        fs::write(thumbnails_dst.join(&thumbnail_name), jpeg)
+            .expect("Failed to write PDF thumbnail");
+
+        thumbnails.push(PdfThumbnail {
+            attachment: file_name,
+            url: format!("/static/thumbnails/{}", thumbnail_name),
+            width: thumbnail.width(),
+            height: thumbnail.height(),
+        });
+    }
+
+    thumbnails
+}
+
+fn render_first_page(path: &Path) -> Option<image::RgbImage> {
+    use hayro::vello_cpu::color::palette::css::WHITE;
+
+    let pdf = hayro::hayro_syntax::Pdf::new(fs::read(path).ok()?).ok()?;
+    let pages = pdf.pages();
+    let page = pages.first()?;
+    let scale = THUMBNAIL_WIDTH / page.render_dimensions().0;
+    let pixmap = hayro::render(
+        page,
+        &hayro::RenderCache::new(),
+        &Default::default(),
+        &hayro::RenderSettings::default(),
+        &hayro::PixmapSettings {
+            x_scale: scale,
+            y_scale: scale,
+            bg_color: WHITE,
+        },
+    );
+    // Opaque on white, so the premultiplied pixels are plain RGBA
+    let (width, height) = (pixmap.width() as u32, pixmap.height() as u32);
+    let rgba = image::RgbaImage::from_raw(width, height, pixmap.data_as_u8_slice().to_vec())?;
+    Some(image::DynamicImage::ImageRgba8(rgba).to_rgb8())
+}
+
+fn write_thumbnail_manifest(out_dir: &Path, thumbnails: &[PdfThumbnail]) {
+    let mut code = String::from("pub static PDF_THUMBNAILS: &[Thumbnail] = &[\n");
+    for thumbnail in thumbnails {
+        writeln!(
+            code,
+            "    Thumbnail {{ attachment: {:?}, url: {:?}, width: {}, height: {} }},",
+            thumbnail.attachment, thumbnail.url, thumbnail.width, thumbnail.height
+        )
+        .unwrap();
+    }
+    code.push_str("];\n");
+    fs::write(out_dir.join("thumbnail_manifest.rs"), code)
+        .expect("Failed to write thumbnail manifest");
+}
+
+// `styles.3f9a1c2b.css` style names produced by a previous build
+fn is_fingerprinted(file_name: &str) -> bool {
+    let mut parts = file_name.rsplit('.');
+    let (Some(_ext), Some(hash), Some(_stem)) = (parts.next(), parts.next(), parts.next()) else {
+        return false;
+    };
+    hash.len() == FINGERPRINT_LEN && hash.chars().all(|ch| ch.is_ascii_hexdigit())
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).expect("Failed to read static directory") {
+        let path = entry.expect("Failed to read static entry").path();
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+struct Asset {
+    path: PathBuf,
+    hashed_path: PathBuf,
+    original_url: String,
+    hashed_url: String,
+    hash: String,
+    gzip: Option<PathBuf>,
+    brotli: Option<PathBuf>,
+}
+
+// Give every static file a content-hashed sibling next to the original.
+fn fingerprint_assets(static_dst: &Path) -> Vec<Asset> {
+    let mut files = Vec::new();
+    collect_files(static_dst, &mut files);
+    files.sort();
+
+    let mut assets = Vec::new();
+    for path in files {
+        let file_name = path.file_name().unwrap().to_string_lossy().to_string();
+        if is_fingerprinted(&file_name) || file_name.ends_with(".gz") || file_name.ends_with(".br")
+        {
+            // Stale output from an earlier build; it is regenerated below if still current
+            fs::remove_file(&path).expect("Failed to remove stale generated asset");
+            continue;
+        }
+        let Some((stem, ext)) = file_name.rsplit_once('.') else {
+            continue;
+        };
+
+        let bytes = fs::read(&path).expect("Failed to read static asset");
+        let digest = Sha256::digest(&bytes);
+        let hash: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+        let hashed_name = format!("{}.{}.{}", stem, &hash[..FINGERPRINT_LEN], ext);
+        let hashed_path = path.with_file_name(&hashed_name);
+        link_or_copy(&path, &hashed_path);
+
+        let relative = path
+            .strip_prefix(static_dst)
+            .unwrap()
+            .to_string_lossy()
+            .replace('\\', "/");
+        let original_url = format!("/static/{}", relative);
+        let hashed_url = match original_url.rsplit_once('/') {
+            Some((dir, _)) => format!("{}/{}", dir, hashed_name),
+            None => hashed_name,
+        };
+        assets.push(Asset {
+            path,
+            hashed_path,
+            original_url,
+            hashed_url,
+            hash,
+            gzip: None,
+            brotli: None,
+        });
+    }
+
+    assets
+}
+
+fn link_or_copy(src: &Path, dst: &Path) {
+    if fs::hard_link(src, dst).is_err() {
+        fs::copy(src, dst).expect("Failed to duplicate generated asset");
+    }
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+// Write `.gz` and `.br` next to both the original and the fingerprinted copy
+// of every text asset, skipping encodings that don't actually save bytes.
+fn precompress_assets(assets: &mut [Asset]) {
+    for asset in assets.iter_mut() {
+        let compressible = asset
+            .path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| COMPRESSIBLE_EXTENSIONS.contains(&ext));
+        if !compressible {
+            continue;
+        }
+        let bytes = fs::read(&asset.path).expect("Failed to read static asset");
+
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        gzip.write_all(&bytes).expect("Failed to gzip asset");
+        let gzip = gzip.finish().expect("Failed to gzip asset");
+
+        let mut brotli = Vec::new();
+        {
+            let mut writer = brotli::CompressorWriter::new(&mut brotli, 4096, 11, 22);
+            writer
+                .write_all(&bytes)
+                .expect("Failed to brotli-compress asset");
+        }
+
+        for (encoded, suffix) in [(gzip, ".gz"), (brotli, ".br")] {
+            if encoded.len() >= bytes.len() {
+                continue;
+            }
+            let path = sibling(&asset.path, suffix);
+            fs::write(&path, encoded).expect("Failed to write compressed asset");
+            link_or_copy(&path, &sibling(&asset.hashed_path, suffix));
+            if suffix == ".gz" {
+                asset.gzip = Some(path);
+            } else {
+                asset.brotli = Some(path);
+            }
+        }
+    }
+}
+
+fn write_asset_manifest(out_dir: &Path, assets: &[Asset]) {
+    let mut code = String::from("pub static ASSET_MANIFEST: &[(&str, &str)] = &[\n");
+    for asset in assets {
+        writeln!(
+            code,
+            "    ({:?}, {:?}),",
+            asset.original_url, asset.hashed_url
+        )
+        .unwrap();
+    }
+    code.push_str("];\n");
+    fs::write(out_dir.join("asset_manifest.rs"), code).expect("Failed to write asset manifest");
+}
+
+fn content_type_for(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match ext.as_str() {
+        "css" => "text/css; charset=utf-8",
+        "js" => "text/javascript; charset=utf-8",
+        "html" => "text/html; charset=utf-8",
+        "json" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "vtt" => "text/vtt; charset=utf-8",
+        "svg" => "image/svg+xml",
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "ico" => "image/x-icon",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+// With the `embed-static` feature every asset is compiled into the binary,
+// reachable under both its original and its fingerprinted path.
+fn write_embedded_assets(out_dir: &Path, assets: &[Asset]) {
+    let include = |path: &Option<PathBuf>| match path {
+        Some(path) => format!("Some(include_bytes!({:?}))", path.display().to_string()),
+        None => "None".to_string(),
+    };
+
+    let mut code = String::new();
+    for (index, asset) in assets.iter().enumerate() {
+        writeln!(
+            code,
+            "static ASSET_{}: &[u8] = include_bytes!({:?});",
+            index,
+            asset.path.display().to_string()
+        )
+        .unwrap();
+        writeln!(
+            code,
+            "static ASSET_{}_GZ: Option<&[u8]> = {};",
+            index,
+            include(&asset.gzip)
+        )
+        .unwrap();
+        writeln!(
+            code,
+            "static ASSET_{}_BR: Option<&[u8]> = {};",
+            index,
+            include(&asset.brotli)
+        )
+        .unwrap();
+    }
+
+    code.push_str("pub static EMBEDDED_ASSETS: &[EmbeddedAsset] = &[\n");
+    for (index, asset) in assets.iter().enumerate() {
+        for url in [&asset.original_url, &asset.hashed_url] {
+            writeln!(
+                code,
+                "    EmbeddedAsset {{ path: {:?}, content_type: {:?}, etag: {:?}, bytes: ASSET_{i}, gzip: ASSET_{i}_GZ, brotli: ASSET_{i}_BR }},",
+                url.trim_start_matches("/static"),
+                content_type_for(&asset.path),
+                &asset.hash[..16],
+                i = index
+            )
+            .unwrap();
+        }
+    }
+    code.push_str("];\n");
+    fs::write(out_dir.join("embedded_assets.rs"), code).expect("Failed to write embedded assets");
+}
+
 fn main() {
     let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
     let static_src = PathBuf::from("static");
@@ -26,4 +526,88 @@ fn main() {
     if static_src.exists() {
         copy_dir_all(&static_src, &static_dst).expect("Failed to copy static files");
     }
+
+    let images_src = static_src.join("media/images");
+    let images = if images_src.exists() {
+        generate_responsive_images(&images_src, &static_dst)
+    } else {
+        Vec::new()
+    };
+    write_image_manifest(&out_dir, &images);
+
+    let thumbnails = if static_src.exists() {
+        generate_pdf_thumbnails(&static_src, &static_dst)
+    } else {
+        Vec::new()
+    };
+    write_thumbnail_manifest(&out_dir, &thumbnails);
+
+    let mut assets = if static_dst.exists() {
+        fingerprint_assets(&static_dst)
+    } else {
+        Vec::new()
+    };
+    precompress_assets(&mut assets);
+    write_asset_manifest(&out_dir, &assets);
+
+    if std::env::var_os("CARGO_FEATURE_EMBED_STATIC").is_some() {
+        write_embedded_assets(&out_dir, &assets);
+    }
+
+    println!("cargo:rustc-env=GIT_COMMIT={}", git_commit());
+    println!("cargo:rustc-env=CONTENT_TIMESTAMP={}", content_timestamp());
+}
+
+// Reported by /healthz. CI can pass GIT_COMMIT when building from a tarball.
+fn git_commit() -> String {
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT");
+    if let Ok(commit) = std::env::var("GIT_COMMIT") {
+        return commit;
+    }
+
+    // Rebuild when HEAD moves, whether by checkout or by a new commit
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    if let Some(head) = fs::read_to_string(".git/HEAD")
+        .ok()
+        .and_then(|head| head.strip_prefix("ref: ").map(|r| r.trim().to_string()))
+    {
+        println!("cargo:rerun-if-changed=.git/{}", head);
+    }
+
+    std::process::Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|commit| commit.trim().to_string())
+        .filter(|commit| !commit.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+// Unix time the page content was last changed, used for `Last-Modified`: the
+// HEAD commit's time, or SOURCE_DATE_EPOCH for reproducible builds. Runs
+// after git_commit, which already reruns the script when HEAD moves.
+fn content_timestamp() -> u64 {
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    if let Some(epoch) = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse().ok())
+    {
+        return epoch;
+    }
+
+    std::process::Command::new("git")
+        .args(["log", "-1", "--format=%ct"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .and_then(|time| time.trim().parse().ok())
+        .unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .expect("System clock is before 1970")
+                .as_secs()
+        })
 }
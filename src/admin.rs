@@ -0,0 +1,95 @@
+use crate::client_ip::ClientIp;
+use crate::models::AppState;
+use axum::{
+    extract::{OriginalUri, Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+/// Guards `/admin`. Accepts `Authorization: Bearer <ADMIN_TOKEN>`, or HTTP
+/// Basic with the token as the password so a browser can sign in. Without
+/// a configured token the admin area doesn't exist at all.
+/// Every admin request, allowed or not, is recorded in the audit log.
+pub async fn require_admin(
+    State(state): State<Arc<AppState>>,
+    ClientIp(ip): ClientIp,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(token) = state.config.admin_token.as_deref() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    // Nesting strips the /admin prefix from the request's own URI
+    let path = request
+        .extensions()
+        .get::<OriginalUri>()
+        .map_or_else(|| request.uri().path(), |uri| uri.path())
+        .to_string();
+    let method = request.method().clone();
+
+    if !is_authorized(request.headers(), token) {
+        tracing::warn!(
+            target: crate::audit::TARGET,
+            action = "admin_rejected",
+            %method,
+            %path,
+            client_ip = %ip,
+            "Rejected admin request to {}",
+            path
+        );
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Basic realm=\"admin\"")],
+            "Unauthorized",
+        )
+            .into_response();
+    }
+
+    let mut response = next.run(request).await;
+    tracing::info!(
+        target: crate::audit::TARGET,
+        action = "admin_request",
+        %method,
+        %path,
+        client_ip = %ip,
+        status = response.status().as_u16(),
+        "Admin request to {}",
+        path
+    );
+    response
+        .headers_mut()
+        .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    response
+}
+
+fn is_authorized(headers: &HeaderMap, token: &str) -> bool {
+    let Some(authorization) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+
+    let presented = if let Some(bearer) = authorization.strip_prefix("Bearer ") {
+        bearer.trim().to_string()
+    } else if let Some(basic) = authorization.strip_prefix("Basic ") {
+        let Ok(decoded) = base64::engine::general_purpose::STANDARD.decode(basic.trim()) else {
+            return false;
+        };
+        let decoded = String::from_utf8_lossy(&decoded);
+        match decoded.split_once(':') {
+            Some((_, password)) => password.to_string(),
+            None => return false,
+        }
+    } else {
+        return false;
+    };
+
+    // Comparing digests keeps the comparison time independent of the token
+    Sha256::digest(presented.as_bytes()) == Sha256::digest(token.as_bytes())
+}
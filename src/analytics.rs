@@ -0,0 +1,121 @@
+use crate::models::VisitRecord;
+use crate::sessions::{self, SessionReport};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+const TOP_ENTRIES: usize = 10;
+
+/// Query parameters shared by the analytics views. Bots are left out unless
+/// `?bots=true` is given.
+#[derive(Deserialize, Default, Clone, Debug)]
+pub struct VisitFilter {
+    #[serde(default)]
+    pub bots: bool,
+}
+
+impl VisitFilter {
+    pub fn matches(&self, visit: &VisitRecord) -> bool {
+        self.bots || !visit.is_bot
+    }
+}
+
+#[derive(Debug)]
+pub struct Summary {
+    pub page_views: usize,
+    pub unique_visitors: usize,
+    pub bot_visits: usize,
+    pub not_modified: usize,
+    pub cache_hit_rate: Option<f64>,
+    pub top_pages: Vec<(String, usize)>,
+    pub top_referrers: Vec<(String, usize)>,
+    pub browsers: Vec<(String, usize)>,
+    pub systems: Vec<(String, usize)>,
+    pub devices: Vec<(String, usize)>,
+    pub countries: Vec<(String, usize)>,
+    pub regions: Vec<(String, usize)>,
+    // Attachments fetched with `?download=true`, counted from the persisted visits
+    pub downloads: Vec<(String, usize)>,
+    pub sessions: SessionReport,
+}
+
+impl Summary {
+    pub fn tables(&self) -> [(&'static str, &[(String, usize)]); 7] {
+        [
+            ("Top pages", &self.top_pages),
+            ("Referrers", &self.top_referrers),
+            ("Browsers", &self.browsers),
+            ("Operating systems", &self.systems),
+            ("Devices", &self.devices),
+            ("Countries", &self.countries),
+            ("Regions", &self.regions),
+        ]
+    }
+
+    pub fn cache_hit_percent(&self) -> String {
+        match self.cache_hit_rate {
+            Some(rate) => format!("{:.0}%", rate * 100.0),
+            None => "n/a".to_string(),
+        }
+    }
+}
+
+pub fn summarize(visits: &[VisitRecord], filter: &VisitFilter) -> Summary {
+    let matching: Vec<&VisitRecord> = visits.iter().filter(|v| filter.matches(v)).collect();
+
+    // Visitor ids change daily, so this counts daily uniques
+    let unique_visitors = matching
+        .iter()
+        .map(|visit| visit.visitor.as_str())
+        .collect::<HashSet<_>>()
+        .len();
+
+    let cached: Vec<bool> = matching
+        .iter()
+        .filter_map(|visit| visit.cache_hit)
+        .collect();
+    let cache_hit_rate = (!cached.is_empty())
+        .then(|| cached.iter().filter(|hit| **hit).count() as f64 / cached.len() as f64);
+
+    Summary {
+        page_views: matching.len(),
+        unique_visitors,
+        bot_visits: visits.iter().filter(|visit| visit.is_bot).count(),
+        not_modified: matching.iter().filter(|visit| visit.status == 304).count(),
+        cache_hit_rate,
+        top_pages: top(matching.iter().map(|visit| visit.page.clone())),
+        top_referrers: top(matching.iter().filter_map(|visit| visit.referrer.clone())),
+        browsers: top(matching.iter().map(|visit| visit.browser.clone())),
+        systems: top(matching.iter().map(|visit| visit.os.clone())),
+        devices: top(matching
+            .iter()
+            .map(|visit| visit.device.as_str().to_string())),
+        countries: top(matching.iter().filter_map(|visit| visit.country.clone())),
+        regions: top(matching.iter().filter_map(|visit| {
+            let region = visit.region.as_ref()?;
+            Some(match &visit.country {
+                Some(country) => format!("{}, {}", region, country),
+                None => region.clone(),
+            })
+        })),
+        downloads: top(matching.iter().filter_map(|visit| {
+            let name = visit
+                .page
+                .strip_prefix("/attachments/")?
+                .strip_suffix("?download=true")?;
+            (visit.status == 200).then(|| name.to_string())
+        })),
+        sessions: sessions::report(&sessions::sessionize(matching.iter().copied())),
+    }
+}
+
+/// Most frequent values first, ties broken alphabetically.
+pub fn top(values: impl Iterator<Item = String>) -> Vec<(String, usize)> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for value in values {
+        *counts.entry(value).or_insert(0) += 1;
+    }
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts.truncate(TOP_ENTRIES);
+    counts
+}
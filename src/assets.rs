@@ -0,0 +1,94 @@
+use axum::{
+    extract::Request,
+    http::{HeaderValue, header},
+    middleware::Next,
+    response::Response,
+};
+
+use chrono::{DateTime, Utc};
+use std::borrow::Cow;
+
+#[cfg(not(feature = "embed-static"))]
+pub const STATIC_PATH: &str = concat!(env!("OUT_DIR"), "/static");
+
+// Generated by build.rs: (original URL, content-hashed URL) for every static file
+include!(concat!(env!("OUT_DIR"), "/asset_manifest.rs"));
+
+/// When the content compiled into this binary last changed (the HEAD commit
+/// time, set by build.rs). Embedded files have no mtime, so this stands in.
+pub fn content_timestamp() -> DateTime<Utc> {
+    env!("CONTENT_TIMESTAMP")
+        .parse()
+        .ok()
+        .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+        .expect("CONTENT_TIMESTAMP is not a valid timestamp")
+}
+
+pub const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+pub const SHORT_CACHE_CONTROL: &str = "public, max-age=300";
+
+/// Maps a `/static/...` URL to its fingerprinted equivalent. Unknown paths are
+/// returned unchanged so templates never break on a missing entry.
+pub fn asset_url(path: &str) -> &str {
+    ASSET_MANIFEST
+        .iter()
+        .find(|(original, _)| *original == path)
+        .map(|(_, hashed)| *hashed)
+        .unwrap_or(path)
+}
+
+/// Reads a file by its path relative to `static/`, from the binary when the
+/// `embed-static` feature is on and from `STATIC_PATH` otherwise.
+pub async fn read_static(relative: &str) -> std::io::Result<Cow<'static, [u8]>> {
+    #[cfg(feature = "embed-static")]
+    {
+        crate::embedded::get(relative)
+            .map(|asset| Cow::Borrowed(asset.bytes))
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound))
+    }
+    #[cfg(not(feature = "embed-static"))]
+    {
+        let path = std::path::Path::new(STATIC_PATH).join(relative);
+        tokio::fs::read(path).await.map(Cow::Owned)
+    }
+}
+
+/// Whether a file exists under `static/`, without reading it.
+pub async fn static_exists(relative: &str) -> bool {
+    #[cfg(feature = "embed-static")]
+    {
+        crate::embedded::get(relative).is_some()
+    }
+    #[cfg(not(feature = "embed-static"))]
+    {
+        let path = std::path::Path::new(STATIC_PATH).join(relative);
+        tokio::fs::metadata(path)
+            .await
+            .is_ok_and(|metadata| metadata.is_file())
+    }
+}
+
+pub fn is_fingerprinted(path: &str) -> bool {
+    ASSET_MANIFEST.iter().any(|(_, hashed)| *hashed == path)
+}
+
+/// Long-lived caching for fingerprinted files, a short max-age for everything
+/// else under `/static`.
+pub async fn static_cache_control(request: Request, next: Next) -> Response {
+    // Nested under `/static`, so the prefix has already been stripped
+    let path = format!("/static{}", request.uri().path());
+    let mut response = next.run(request).await;
+
+    if response.status().is_success() || response.status().as_u16() == 304 {
+        let value = if is_fingerprinted(&path) {
+            IMMUTABLE_CACHE_CONTROL
+        } else {
+            SHORT_CACHE_CONTROL
+        };
+        response
+            .headers_mut()
+            .insert(header::CACHE_CONTROL, HeaderValue::from_static(value));
+    }
+
+    response
+}
@@ -0,0 +1,149 @@
+use crate::assets::read_static;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+#[derive(Clone, Debug)]
+pub struct AttachmentInfo {
+    pub name: String,
+    pub content_type: &'static str,
+    pub size: u64,
+    pub sha256: String,
+    /// Rendered first page, for PDFs the build could draw
+    pub thumbnail: Option<&'static Thumbnail>,
+}
+
+#[derive(Debug)]
+pub struct Thumbnail {
+    pub attachment: &'static str,
+    pub url: &'static str,
+    pub width: u32,
+    pub height: u32,
+}
+
+// Generated by build.rs from the PDFs in static/
+include!(concat!(env!("OUT_DIR"), "/thumbnail_manifest.rs"));
+
+impl AttachmentInfo {
+    pub fn is_pdf(&self) -> bool {
+        self.content_type == "application/pdf"
+    }
+
+    // PDFs and images open in the browser, everything else is a download
+    pub fn is_inline(&self) -> bool {
+        self.is_pdf() || self.content_type.starts_with("image/")
+    }
+
+    pub fn short_hash(&self) -> &str {
+        &self.sha256[..12]
+    }
+
+    pub fn human_size(&self) -> String {
+        const KB: u64 = 1024;
+        const MB: u64 = KB * 1024;
+        if self.size >= MB {
+            format!("{:.1} MB", self.size as f64 / MB as f64)
+        } else if self.size >= KB {
+            format!("{} KB", self.size / KB)
+        } else {
+            format!("{} B", self.size)
+        }
+    }
+}
+
+/// Allowed attachments, built once at startup from the files the content
+/// references. Anything not listed here is never served.
+pub struct AttachmentManifest {
+    entries: HashMap<String, AttachmentInfo>,
+}
+
+impl AttachmentManifest {
+    pub async fn load(names: &[String]) -> Self {
+        let mut entries = HashMap::new();
+
+        for name in names {
+            if !is_safe_name(name) {
+                tracing::warn!("Rejected attachment with unsafe name: {}", name);
+                continue;
+            }
+
+            let bytes = match read_static(name).await {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    tracing::warn!("Attachment {} is missing: {}", name, e);
+                    continue;
+                }
+            };
+
+            let info = AttachmentInfo {
+                name: name.clone(),
+                content_type: detect_content_type(name, &bytes),
+                size: bytes.len() as u64,
+                sha256: hex_digest(&bytes),
+                thumbnail: PDF_THUMBNAILS
+                    .iter()
+                    .find(|thumbnail| thumbnail.attachment == name),
+            };
+            tracing::info!(
+                "Registered attachment {} ({}, {} bytes, sha256 {})",
+                info.name,
+                info.content_type,
+                info.size,
+                info.sha256
+            );
+            entries.insert(name.clone(), info);
+        }
+
+        Self { entries }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&AttachmentInfo> {
+        self.entries.get(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+}
+
+fn is_safe_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && !name.contains(['/', '\\', '"'])
+        && !name.contains("..")
+}
+
+// Sniff well-known signatures first and fall back to the file extension
+fn detect_content_type(name: &str, bytes: &[u8]) -> &'static str {
+    if bytes.starts_with(b"%PDF-") {
+        return "application/pdf";
+    }
+    if bytes.starts_with(b"PK\x03\x04") {
+        return "application/zip";
+    }
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return "image/jpeg";
+    }
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return "image/png";
+    }
+
+    let extension = name
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "jpg" | "jpeg" => "image/jpeg",
+        "png" => "image/png",
+        "txt" => "text/plain; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+
+fn hex_digest(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
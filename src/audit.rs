@@ -0,0 +1,301 @@
+use chrono::{SecondsFormat, Utc};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use tracing::field::{Field, Visit};
+use tracing_subscriber::layer::Context;
+
+/// Events logged with `target: "audit"` end up in the audit log, e.g.
+/// `tracing::info!(target: audit::TARGET, action = "visits_purged", purged)`.
+pub const TARGET: &str = "audit";
+
+// prev_hash of the first entry
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+// The subscriber is global and set up before AppState, so its health is too
+static OPENED: OnceLock<PathBuf> = OnceLock::new();
+static PROBLEM: Mutex<Option<String>> = Mutex::new(None);
+static WRITE_FAILURES: AtomicU64 = AtomicU64::new(0);
+
+/// Append-only JSON Lines file of audit events. Every entry carries the
+/// hash of the one before it and its own hash over everything else, so
+/// editing, inserting or removing a line breaks the chain from there on.
+pub struct AuditLog {
+    path: PathBuf,
+    state: Mutex<Chain>,
+}
+
+struct Chain {
+    file: File,
+    last_hash: String,
+    // Entries lost since the last successful write
+    dropped: u64,
+    // The file may end in half a line (a failed write or a crash)
+    needs_newline: bool,
+}
+
+// What a pass over an existing audit file found
+struct Scan {
+    entries: usize,
+    last_hash: String,
+    error: Option<String>,
+}
+
+impl AuditLog {
+    /// Opens (or creates) the log and continues the chain from its last
+    /// entry. A broken chain is reported in the warning, not repaired. The
+    /// file stays locked while open, so only one process can append to it.
+    pub fn open(path: &Path) -> Result<(Self, Option<String>), String> {
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        // A second writer would continue the chain from a stale hash and fork it
+        file.try_lock().map_err(|e| match e {
+            TryLockError::WouldBlock => {
+                format!("{} is in use by another process", path.display())
+            }
+            TryLockError::Error(e) => format!("Failed to lock {}: {}", path.display(), e),
+        })?;
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let scan = scan(&contents);
+
+        let warning = scan.error.map(|error| {
+            format!(
+                "Audit log {} failed verification ({}), appending anyway",
+                path.display(),
+                error
+            )
+        });
+        let log = Self {
+            path: path.to_path_buf(),
+            state: Mutex::new(Chain {
+                file,
+                last_hash: scan.last_hash,
+                dropped: 0,
+                needs_newline: !contents.is_empty() && !contents.ends_with('\n'),
+            }),
+        };
+        let _ = OPENED.set(path.to_path_buf());
+        Ok((log, warning))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn append(&self, mut entry: Map<String, Value>) {
+        let mut chain = self.state.lock().unwrap();
+        // Lost entries can't be chained, but the gap is recorded in the next one
+        if chain.dropped > 0 {
+            entry.insert("dropped_before".to_string(), chain.dropped.into());
+        }
+        entry.insert(
+            "prev_hash".to_string(),
+            Value::String(chain.last_hash.clone()),
+        );
+        let hash = hash_entry(&entry);
+        entry.insert("hash".to_string(), Value::String(hash.clone()));
+
+        let mut line = serde_json::to_string(&entry).expect("Failed to serialize audit entry");
+        line.push('\n');
+        // Don't glue this entry to a torn line
+        if chain.needs_newline {
+            line.insert(0, '\n');
+        }
+        // One write per entry so a crash can't interleave half lines
+        let written = chain
+            .file
+            .write_all(line.as_bytes())
+            .and_then(|()| chain.file.sync_data());
+        match written {
+            Ok(()) => {
+                chain.last_hash = hash;
+                chain.needs_newline = false;
+                if chain.dropped > 0 {
+                    chain.dropped = 0;
+                    *PROBLEM.lock().unwrap() = None;
+                }
+            }
+            Err(e) => {
+                chain.dropped += 1;
+                chain.needs_newline = true;
+                WRITE_FAILURES.fetch_add(1, Ordering::Relaxed);
+                let problem = format!("Failed to write audit log {}: {}", self.path.display(), e);
+                // Logging it would come straight back here
+                eprintln!("{}", problem);
+                *PROBLEM.lock().unwrap() = Some(problem);
+            }
+        }
+    }
+}
+
+/// Notes that the configured audit log couldn't be opened, so `/readyz`
+/// keeps failing until it is fixed.
+pub fn report_unavailable(problem: String) {
+    *PROBLEM.lock().unwrap() = Some(problem);
+}
+
+/// For readiness checks: the audit log is open and its last write worked.
+pub fn check() -> Result<String, String> {
+    if let Some(problem) = PROBLEM.lock().unwrap().clone() {
+        return Err(problem);
+    }
+    Ok(OPENED
+        .get()
+        .map_or_else(|| "disabled".to_string(), |path| path.display().to_string()))
+}
+
+/// Audit entries that could not be written since startup.
+pub fn write_failures() -> u64 {
+    WRITE_FAILURES.load(Ordering::Relaxed)
+}
+
+/// Writes `audit` events to the `AuditLog`, one entry per event with its
+/// fields and a timestamp.
+pub struct AuditLayer {
+    log: AuditLog,
+}
+
+impl AuditLayer {
+    pub fn new(log: AuditLog) -> Self {
+        Self { log }
+    }
+}
+
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for AuditLayer {
+    fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+        let mut entry = Map::new();
+        entry.insert(
+            "timestamp".to_string(),
+            Value::String(Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)),
+        );
+        entry.insert(
+            "level".to_string(),
+            Value::String(event.metadata().level().to_string()),
+        );
+        event.record(&mut FieldVisitor(&mut entry));
+        self.log.append(entry);
+    }
+}
+
+struct FieldVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), format!("{:?}", value).into());
+    }
+}
+
+// SHA-256 over the entry serialized without its own hash; serde_json keeps
+// map keys sorted, so re-serializing a parsed line gives the same bytes
+fn hash_entry(entry: &Map<String, Value>) -> String {
+    let bytes = serde_json::to_vec(entry).expect("Failed to serialize audit entry");
+    Sha256::digest(&bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn scan(contents: &str) -> Scan {
+    let mut scan = Scan {
+        entries: 0,
+        last_hash: GENESIS.to_string(),
+        error: None,
+    };
+    for (index, line) in contents.lines().enumerate() {
+        let line_number = index + 1;
+        if line.is_empty() {
+            continue;
+        }
+        let Ok(mut entry) = serde_json::from_str::<Map<String, Value>>(line) else {
+            scan.error
+                .get_or_insert_with(|| format!("line {} is not a JSON object", line_number));
+            continue;
+        };
+        let Some(Value::String(hash)) = entry.remove("hash") else {
+            scan.error
+                .get_or_insert_with(|| format!("line {} has no hash", line_number));
+            continue;
+        };
+        if entry.get("prev_hash").and_then(Value::as_str) != Some(scan.last_hash.as_str()) {
+            scan.error.get_or_insert_with(|| {
+                format!("line {} does not follow the previous entry", line_number)
+            });
+        } else if hash_entry(&entry) != hash {
+            scan.error
+                .get_or_insert_with(|| format!("line {} was modified", line_number));
+        }
+        scan.entries += 1;
+        scan.last_hash = hash;
+    }
+    scan
+}
+
+/// `ethan-web verify-audit [PATH]`: checks the hash chain and exits
+/// non-zero at the first broken entry. Lines cut off the end of the file
+/// leave a valid chain, so compare the last hash with a copy kept elsewhere.
+pub fn run_cli(args: &[String], default_path: Option<&Path>) -> i32 {
+    let path = match (args.first(), default_path) {
+        (Some(path), _) => PathBuf::from(path),
+        (None, Some(path)) => path.to_path_buf(),
+        (None, None) => {
+            eprintln!("No audit log to verify: AUDIT_LOG is empty and no path was given");
+            return 2;
+        }
+    };
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", path.display(), e);
+            return 2;
+        }
+    };
+
+    let scan = scan(&contents);
+    match scan.error {
+        Some(error) => {
+            eprintln!("{}: {}", path.display(), error);
+            1
+        }
+        None => {
+            println!(
+                "{}: {} entries, chain intact, last hash {}",
+                path.display(),
+                scan.entries,
+                scan.last_hash
+            );
+            0
+        }
+    }
+}
@@ -1,7 +1,23 @@
+use crate::compression::Encoding;
+use crate::security::ScriptHashes;
+use axum::body::Bytes;
+use chrono::{DateTime, Utc};
 use moka::future::Cache;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Clone)]
+pub struct CachedPage {
+    pub body: Bytes,
+    // Strong validator of the identity HTML; encodings append a suffix
+    pub etag: String,
+    pub scripts: ScriptHashes,
+}
 
 pub struct CacheManager {
-    cache: Cache<String, String>,
+    cache: Cache<String, CachedPage>,
+    last_modified: DateTime<Utc>,
+    hits: AtomicU64,
+    misses: AtomicU64,
 }
 
 impl CacheManager {
@@ -12,23 +28,50 @@ impl CacheManager {
             .time_to_live(std::time::Duration::from_secs(3600)) // 1 hour
             .build();
 
-        Self { cache }
+        Self {
+            cache,
+            last_modified: crate::assets::content_timestamp(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
     }
 
-    pub async fn get(&self, key: &str) -> Option<String> {
+    pub async fn get(&self, key: &str) -> Option<CachedPage> {
         self.cache.get(key).await
     }
 
-    pub async fn set(&self, key: String, value: String) {
+    pub async fn set(&self, key: String, value: CachedPage) {
         self.cache.insert(key, value).await;
     }
 
-    pub fn make_key(&self, path: &str) -> String {
-        path.to_string()
+    // One entry per encoding so compressed pages are reused as-is
+    pub fn make_key(&self, path: &str, encoding: Encoding) -> String {
+        format!("{}#{}", path, encoding.as_str())
+    }
+
+    /// Counts one page request; a hit means nothing had to be rendered,
+    /// even if the body still had to be compressed.
+    pub fn record(&self, hit: bool) {
+        let counter = if hit { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    // Approximate; moka applies pending writes lazily
+    pub fn entry_count(&self) -> u64 {
+        self.cache.entry_count()
     }
 
-    // Maybe doesn't work?
-    pub async fn invalidate(&self) {
-        self.cache.invalidate_all();
+    /// When the content compiled into this binary last changed; used as
+    /// `Last-Modified` so restarts don't invalidate browser caches.
+    pub fn last_modified(&self) -> DateTime<Utc> {
+        self.last_modified
     }
 }
@@ -0,0 +1,122 @@
+use crate::models::AppState;
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{HeaderMap, StatusCode, request::Parts},
+};
+use ipnet::IpNet;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+/// The address of the visitor, taken from forwarding headers when the
+/// connection comes from a trusted proxy and from the socket otherwise.
+#[derive(Clone, Copy, Debug)]
+pub struct ClientIp(pub IpAddr);
+
+impl FromRequestParts<Arc<AppState>> for ClientIp {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        // Already resolved by an outer middleware for this request
+        if let Some(client_ip) = parts.extensions.get::<ClientIp>() {
+            return Ok(*client_ip);
+        }
+
+        let ConnectInfo(peer) = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .copied()
+            .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+        let client_ip = ClientIp(resolve(
+            peer.ip(),
+            &parts.headers,
+            &state.config.trusted_proxies,
+        ));
+        parts.extensions.insert(client_ip);
+        Ok(client_ip)
+    }
+}
+
+fn is_trusted(ip: IpAddr, trusted: &[IpNet]) -> bool {
+    trusted.iter().any(|network| network.contains(&ip))
+}
+
+/// Walks the forwarding chain from the nearest hop outwards and returns the
+/// first address that is not a trusted proxy. Headers are ignored entirely
+/// unless the peer itself is trusted, since anyone can send them.
+pub fn resolve(peer: IpAddr, headers: &HeaderMap, trusted: &[IpNet]) -> IpAddr {
+    if !is_trusted(peer, trusted) {
+        return peer;
+    }
+
+    // RFC 7239 wins, then the de facto headers
+    let chain = forwarded_chain(headers)
+        .or_else(|| x_forwarded_for_chain(headers))
+        .or_else(|| {
+            header_values(headers, "x-real-ip")
+                .last()
+                .map(|value| vec![parse_node(value)])
+        });
+    let Some(chain) = chain else {
+        return peer;
+    };
+
+    let mut client = peer;
+    for hop in chain.into_iter().rev() {
+        match hop {
+            Some(ip) => {
+                client = ip;
+                if !is_trusted(ip, trusted) {
+                    break;
+                }
+            }
+            // `unknown` or an obfuscated identifier: nothing further is reliable
+            None => break,
+        }
+    }
+    client
+}
+
+fn header_values<'a>(headers: &'a HeaderMap, name: &str) -> impl Iterator<Item = &'a str> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+}
+
+fn x_forwarded_for_chain(headers: &HeaderMap) -> Option<Vec<Option<IpAddr>>> {
+    let chain: Vec<_> = header_values(headers, "x-forwarded-for")
+        .flat_map(|value| value.split(','))
+        .map(|node| parse_node(node.trim()))
+        .collect();
+    (!chain.is_empty()).then_some(chain)
+}
+
+// `Forwarded: for=192.0.2.60;proto=http, for="[2001:db8::17]:4711"`
+fn forwarded_chain(headers: &HeaderMap) -> Option<Vec<Option<IpAddr>>> {
+    let chain: Vec<_> = header_values(headers, "forwarded")
+        .flat_map(|value| value.split(','))
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (key, value) = pair.trim().split_once('=')?;
+                key.trim()
+                    .eq_ignore_ascii_case("for")
+                    .then(|| parse_node(value.trim().trim_matches('"')))
+            })
+        })
+        .collect();
+    (!chain.is_empty()).then_some(chain)
+}
+
+// An address with an optional port: `192.0.2.1`, `192.0.2.1:80`, `[::1]:80`, `::1`
+fn parse_node(node: &str) -> Option<IpAddr> {
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split_once(']')?.0.parse().ok();
+    }
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+    node.parse::<SocketAddr>().ok().map(|addr| addr.ip())
+}
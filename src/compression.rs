@@ -0,0 +1,105 @@
+use axum::http::{HeaderMap, header};
+use std::io::Write;
+
+// Pages are compressed once per cache entry, so favor ratio over speed
+const BROTLI_QUALITY: u32 = 9;
+const BROTLI_WINDOW: u32 = 22;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Identity,
+    Gzip,
+    Brotli,
+}
+
+impl Encoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Identity => "identity",
+            Encoding::Gzip => "gzip",
+            Encoding::Brotli => "br",
+        }
+    }
+}
+
+/// Picks the best encoding the client accepts, preferring brotli over gzip.
+/// Encodings listed with `q=0` are treated as refused.
+pub fn negotiate(headers: &HeaderMap) -> Encoding {
+    let (brotli, gzip) = accepted(headers);
+    if brotli {
+        Encoding::Brotli
+    } else if gzip {
+        Encoding::Gzip
+    } else {
+        Encoding::Identity
+    }
+}
+
+/// Whether the client accepts `encoding`; identity always is.
+#[cfg(feature = "embed-static")]
+pub fn accepts(headers: &HeaderMap, encoding: Encoding) -> bool {
+    let (brotli, gzip) = accepted(headers);
+    match encoding {
+        Encoding::Identity => true,
+        Encoding::Gzip => gzip,
+        Encoding::Brotli => brotli,
+    }
+}
+
+// (brotli, gzip) as listed in `Accept-Encoding`
+fn accepted(headers: &HeaderMap) -> (bool, bool) {
+    let Some(accept) = headers
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return (false, false);
+    };
+
+    let mut brotli = false;
+    let mut gzip = false;
+    for entry in accept.split(',') {
+        let mut parts = entry.split(';');
+        let name = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+        let refused = parts.any(|param| {
+            param
+                .trim()
+                .strip_prefix("q=")
+                .and_then(|q| q.trim().parse::<f32>().ok())
+                .is_some_and(|q| q <= 0.0)
+        });
+        if refused {
+            continue;
+        }
+        match name.as_str() {
+            "br" => brotli = true,
+            "gzip" | "x-gzip" => gzip = true,
+            "*" => {
+                brotli = true;
+                gzip = true;
+            }
+            _ => {}
+        }
+    }
+    (brotli, gzip)
+}
+
+pub fn compress(encoding: Encoding, bytes: &[u8]) -> Vec<u8> {
+    match encoding {
+        Encoding::Identity => bytes.to_vec(),
+        Encoding::Gzip => {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(bytes).expect("Failed to gzip response");
+            encoder.finish().expect("Failed to gzip response")
+        }
+        Encoding::Brotli => {
+            let mut output = Vec::new();
+            {
+                let mut writer =
+                    brotli::CompressorWriter::new(&mut output, 4096, BROTLI_QUALITY, BROTLI_WINDOW);
+                writer.write_all(bytes).expect("Failed to brotli-compress response");
+            }
+            output
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use crate::rate_limit::RateLimit;
+use ipnet::IpNet;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Runtime settings, read once from the environment at startup.
+pub struct Config {
+    /// `BIND_ADDR`, the address the HTTP server listens on
+    pub bind_addr: SocketAddr,
+    /// `VIDEO_BANDWIDTH_LIMIT`, bytes per second per video response; 0 disables the cap
+    pub video_bandwidth_limit: u64,
+    /// `SECURITY_HEADERS`, set to false to send none of the headers below
+    pub security_headers: bool,
+    /// `CSP_REPORT_ONLY`, send the CSP as `Content-Security-Policy-Report-Only`
+    pub csp_report_only: bool,
+    /// `HSTS_MAX_AGE`, seconds for `Strict-Transport-Security`; 0 disables it
+    pub hsts_max_age: u64,
+    /// `REFERRER_POLICY`
+    pub referrer_policy: String,
+    /// `PERMISSIONS_POLICY`
+    pub permissions_policy: String,
+    /// `TRUSTED_PROXIES`, CIDRs whose forwarding headers are believed
+    pub trusted_proxies: Vec<IpNet>,
+    /// `IP_TRUNCATION`, hash only the IPv4 /24 or IPv6 /48 of each visitor
+    pub ip_truncation: bool,
+    /// `VISIT_RETENTION_DAYS`, how long visit records are kept; 0 keeps them forever
+    pub visit_retention_days: u32,
+    /// `VISIT_STORE`, JSON Lines file the visits are appended to; empty keeps
+    /// them in memory only
+    pub visit_store: Option<PathBuf>,
+    /// `ADMIN_TOKEN`, required for `/admin`; the admin area is disabled without it
+    pub admin_token: Option<String>,
+    /// `GEOIP_DATABASE`, path to a MaxMind `.mmdb` file for visit locations
+    pub geoip_database: Option<PathBuf>,
+    /// `TLS_CERT` and `TLS_KEY`, PEM files; HTTPS is enabled when both are
+    /// set, and `BIND_ADDR` then only redirects to it
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    /// `HTTPS_ADDR`
+    pub https_addr: SocketAddr,
+    /// `SHUTDOWN_TIMEOUT`, seconds to let in-flight requests finish after
+    /// SIGTERM/SIGINT
+    pub shutdown_timeout: u64,
+    /// `METRICS_ADDR`, serves `/metrics` on its own listener without auth;
+    /// otherwise `/metrics` is on the main port behind the admin token
+    pub metrics_addr: Option<SocketAddr>,
+    /// `RATE_LIMIT_PAGES`, per client IP, as `per_minute/burst`
+    pub rate_limit_pages: RateLimit,
+    /// `RATE_LIMIT_STATIC`, covering `/static`, `/videos` and `/attachments`
+    pub rate_limit_static: RateLimit,
+    /// `RATE_LIMIT_API`
+    pub rate_limit_api: RateLimit,
+    /// `RATE_LIMIT_ADMIN`
+    pub rate_limit_admin: RateLimit,
+    /// `RATE_LIMIT_ALLOWLIST`, comma-separated IPs or CIDRs that are never limited
+    pub rate_limit_allowlist: Vec<IpNet>,
+}
+
+impl Config {
+    /// Certificate and key paths, when HTTPS is configured.
+    pub fn tls(&self) -> Option<(PathBuf, PathBuf)> {
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => Some((cert.clone(), key.clone())),
+            (None, None) => None,
+            _ => {
+                tracing::warn!("HTTPS needs both TLS_CERT and TLS_KEY, serving plain HTTP");
+                None
+            }
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self {
+            bind_addr: env_or("BIND_ADDR", SocketAddr::from(([0, 0, 0, 0], 3000))),
+            video_bandwidth_limit: env_or("VIDEO_BANDWIDTH_LIMIT", 2 * 1024 * 1024),
+            security_headers: env_or("SECURITY_HEADERS", true),
+            csp_report_only: env_or("CSP_REPORT_ONLY", false),
+            hsts_max_age: env_or("HSTS_MAX_AGE", 365 * 24 * 60 * 60),
+            referrer_policy: env_or(
+                "REFERRER_POLICY",
+                "strict-origin-when-cross-origin".to_string(),
+            ),
+            permissions_policy: env_or(
+                "PERMISSIONS_POLICY",
+                "camera=(), microphone=(), geolocation=(), payment=(), usb=()".to_string(),
+            ),
+            // nginx on the same host
+            trusted_proxies: env_list(
+                "TRUSTED_PROXIES",
+                vec![
+                    IpNet::from(IpAddr::from([127, 0, 0, 1])),
+                    IpNet::from(IpAddr::from(Ipv6Addr::LOCALHOST)),
+                ],
+            ),
+            ip_truncation: env_or("IP_TRUNCATION", false),
+            visit_retention_days: env_or("VISIT_RETENTION_DAYS", 30),
+            visit_store: match std::env::var_os("VISIT_STORE") {
+                Some(path) if path.is_empty() => None,
+                Some(path) => Some(PathBuf::from(path)),
+                None => Some(PathBuf::from("data/visits.jsonl")),
+            },
+            admin_token: std::env::var("ADMIN_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty()),
+            geoip_database: env_path("GEOIP_DATABASE"),
+            tls_cert: env_path("TLS_CERT"),
+            tls_key: env_path("TLS_KEY"),
+            https_addr: env_or("HTTPS_ADDR", SocketAddr::from(([0, 0, 0, 0], 443))),
+            shutdown_timeout: env_or("SHUTDOWN_TIMEOUT", 10),
+            metrics_addr: std::env::var("METRICS_ADDR")
+                .ok()
+                .filter(|addr| !addr.is_empty())
+                .and_then(|addr| {
+                    addr.parse()
+                        .inspect_err(|_| tracing::warn!("Ignoring invalid METRICS_ADDR={:?}", addr))
+                        .ok()
+                }),
+            rate_limit_pages: env_or("RATE_LIMIT_PAGES", RateLimit::new(120, 30)),
+            rate_limit_static: env_or("RATE_LIMIT_STATIC", RateLimit::new(1200, 200)),
+            rate_limit_api: env_or("RATE_LIMIT_API", RateLimit::new(60, 20)),
+            rate_limit_admin: env_or("RATE_LIMIT_ADMIN", RateLimit::new(30, 10)),
+            rate_limit_allowlist: env_list("RATE_LIMIT_ALLOWLIST", vec![]),
+        }
+    }
+}
+
+/// Logging settings, read before the subscriber (and so `Config`) exists.
+pub struct LogConfig {
+    /// `LOG_FORMAT`, `json` for one JSON object per line
+    pub json: bool,
+    /// `LOG_DIR`, also write logs to rotating files here; stdout only when unset
+    pub log_dir: Option<PathBuf>,
+    /// `LOG_MAX_SIZE`, bytes after which a log file is rotated before the day ends
+    pub log_max_size: u64,
+    /// `LOG_RETENTION_DAYS`, how long rotated log files are kept; 0 keeps them forever
+    pub log_retention_days: u32,
+    /// `AUDIT_LOG`, hash-chained JSON Lines file for audit events; empty disables
+    /// it. The file is locked, so each process needs its own
+    pub audit_log: Option<PathBuf>,
+}
+
+impl LogConfig {
+    pub fn from_env() -> Self {
+        Self {
+            json: std::env::var("LOG_FORMAT").is_ok_and(|format| format == "json"),
+            log_dir: env_path("LOG_DIR"),
+            log_max_size: env_or("LOG_MAX_SIZE", 10 * 1024 * 1024),
+            log_retention_days: env_or("LOG_RETENTION_DAYS", 14),
+            audit_log: match std::env::var_os("AUDIT_LOG") {
+                Some(path) if path.is_empty() => None,
+                Some(path) => Some(PathBuf::from(path)),
+                None => Some(PathBuf::from("data/audit.jsonl")),
+            },
+        }
+    }
+}
+
+fn env_path(name: &str) -> Option<PathBuf> {
+    std::env::var_os(name)
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+}
+
+fn env_or<T: FromStr>(name: &str, default: T) -> T {
+    match std::env::var(name) {
+        Ok(value) => value.parse().unwrap_or_else(|_| {
+            tracing::warn!("Ignoring invalid {}={:?}, using the default", name, value);
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+// Comma-separated list; a bare IP is treated as a single-address network
+fn env_list(name: &str, default: Vec<IpNet>) -> Vec<IpNet> {
+    let Ok(value) = std::env::var(name) else {
+        return default;
+    };
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .filter_map(|entry| {
+            let parsed = entry
+                .parse::<IpNet>()
+                .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from));
+            if parsed.is_err() {
+                tracing::warn!("Ignoring invalid network {:?} in {}", entry, name);
+            }
+            parsed.ok()
+        })
+        .collect()
+}
@@ -0,0 +1,158 @@
+use crate::compression::{self, Encoding};
+use crate::range::{
+    ByteRanges, closing_delimiter, if_range_matches, multipart_content_type, parse_byte_ranges,
+    part_header,
+};
+use axum::{
+    body::{Body, Bytes},
+    http::{HeaderMap, Method, StatusCode, Uri, header},
+    response::{IntoResponse, Response},
+};
+use std::ops::RangeInclusive;
+
+pub struct EmbeddedAsset {
+    pub path: &'static str,
+    pub content_type: &'static str,
+    pub etag: &'static str,
+    pub bytes: &'static [u8],
+    pub gzip: Option<&'static [u8]>,
+    pub brotli: Option<&'static [u8]>,
+}
+
+impl EmbeddedAsset {
+    // Best precompressed variant the client accepts, if there is one
+    fn encoded(&self, headers: &HeaderMap) -> Option<(Encoding, &'static [u8])> {
+        let accepted = |encoding| compression::accepts(headers, encoding);
+        self.brotli
+            .filter(|_| accepted(Encoding::Brotli))
+            .map(|bytes| (Encoding::Brotli, bytes))
+            .or_else(|| {
+                self.gzip
+                    .filter(|_| accepted(Encoding::Gzip))
+                    .map(|bytes| (Encoding::Gzip, bytes))
+            })
+    }
+}
+
+// Generated by build.rs when the `embed-static` feature is enabled
+include!(concat!(env!("OUT_DIR"), "/embedded_assets.rs"));
+
+/// Looks up a file by its path relative to `static/`, e.g. `styles.css`.
+pub fn get(relative: &str) -> Option<&'static EmbeddedAsset> {
+    let path = format!("/{}", relative.trim_start_matches('/'));
+    EMBEDDED_ASSETS.iter().find(|asset| asset.path == path)
+}
+
+/// Stand-in for `ServeDir` that answers from the assets compiled into the
+/// binary, with ETag revalidation, precompressed variants and byte ranges
+/// (`If-Range` and multipart included, as for videos).
+pub async fn serve_embedded(method: Method, uri: Uri, headers: HeaderMap) -> Response {
+    // Nested under `/static`, so the path is already relative to it
+    let Some(asset) = get(uri.path()) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    // Embedded files change only with the build
+    let last_modified = crate::assets::content_timestamp();
+    let identity_etag = format!("\"{}\"", asset.etag);
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .filter(|_| if_range_matches(&headers, &identity_etag, Some(last_modified)));
+    // Ranges always address the identity bytes
+    let encoded = if range.is_none() {
+        asset.encoded(&headers)
+    } else {
+        None
+    };
+    let etag = match encoded {
+        Some((encoding, _)) => format!("\"{}-{}\"", asset.etag, encoding.as_str()),
+        None => identity_etag,
+    };
+
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value
+                .split(',')
+                .any(|tag| tag.trim() == etag || tag.trim() == "*")
+        });
+    if not_modified {
+        return (
+            StatusCode::NOT_MODIFIED,
+            [(header::ETAG, etag), (header::VARY, "accept-encoding".to_string())],
+        )
+            .into_response();
+    }
+
+    let len = asset.bytes.len() as u64;
+    let slice =
+        |range: &RangeInclusive<u64>| &asset.bytes[*range.start() as usize..=*range.end() as usize];
+    let (status, content_type, body, content_range) = match parse_byte_ranges(range, len) {
+        ByteRanges::Full => (
+            StatusCode::OK,
+            asset.content_type.to_string(),
+            Bytes::from_static(encoded.map(|(_, bytes)| bytes).unwrap_or(asset.bytes)),
+            None,
+        ),
+        ByteRanges::Partial(ranges) if ranges.len() == 1 => {
+            let range = &ranges[0];
+            let content_range = format!("bytes {}-{}/{}", range.start(), range.end(), len);
+            (
+                StatusCode::PARTIAL_CONTENT,
+                asset.content_type.to_string(),
+                Bytes::from_static(slice(range)),
+                Some(content_range),
+            )
+        }
+        ByteRanges::Partial(ranges) => {
+            let mut body = Vec::new();
+            for range in &ranges {
+                body.extend_from_slice(part_header(asset.content_type, range, len).as_bytes());
+                body.extend_from_slice(slice(range));
+            }
+            body.extend_from_slice(closing_delimiter().as_bytes());
+            (
+                StatusCode::PARTIAL_CONTENT,
+                multipart_content_type(),
+                Bytes::from(body),
+                None,
+            )
+        }
+        ByteRanges::Unsatisfiable => {
+            return (
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(header::CONTENT_RANGE, format!("bytes */{}", len))],
+            )
+                .into_response();
+        }
+    };
+
+    let mut response = Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CONTENT_LENGTH, body.len())
+        .header(header::ETAG, etag)
+        .header(
+            header::LAST_MODIFIED,
+            last_modified
+                .format("%a, %d %b %Y %H:%M:%S GMT")
+                .to_string(),
+        )
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::VARY, "accept-encoding");
+    if let Some(content_range) = content_range {
+        response = response.header(header::CONTENT_RANGE, content_range);
+    }
+    if let Some((encoding, _)) = encoded {
+        response = response.header(header::CONTENT_ENCODING, encoding.as_str());
+    }
+
+    let body = if method == Method::HEAD {
+        Body::empty()
+    } else {
+        Body::from(body)
+    };
+    response.body(body).unwrap()
+}
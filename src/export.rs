@@ -0,0 +1,279 @@
+use crate::models::VisitRecord;
+use crate::visit_store::{self, VisitStore};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+// Records copied out of the store per lock
+const BATCH_SIZE: usize = 500;
+
+const CSV_COLUMNS: [&str; 13] = [
+    "timestamp",
+    "page",
+    "visitor",
+    "status",
+    "cache_hit",
+    "referrer",
+    "browser",
+    "os",
+    "device",
+    "is_bot",
+    "country",
+    "region",
+    "user_agent",
+];
+
+/// Query parameters for the export endpoints, also used by the CLI.
+#[derive(Deserialize, Default, Debug)]
+pub struct ExportFilter {
+    /// First day to include (UTC), e.g. `2025-03-01`
+    pub from: Option<NaiveDate>,
+    /// Last day to include (UTC)
+    pub to: Option<NaiveDate>,
+    /// Only pages starting with this path, e.g. `/projects`
+    pub page: Option<String>,
+    /// `true` exports only bots, `false` only people; everything when absent
+    pub bots: Option<bool>,
+}
+
+impl ExportFilter {
+    pub fn matches(&self, visit: &VisitRecord) -> bool {
+        let day = visit.timestamp.date_naive();
+        self.from.is_none_or(|from| day >= from)
+            && self.to.is_none_or(|to| day <= to)
+            && self
+                .page
+                .as_deref()
+                .is_none_or(|prefix| visit.page.starts_with(prefix))
+            && self.bots.is_none_or(|bots| visit.is_bot == bots)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+impl Format {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Csv => "text/csv; charset=utf-8",
+            Format::Json => "application/json",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Json => "json",
+        }
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown export format {:?}", value)),
+        }
+    }
+}
+
+/// Renders matching records one at a time, so output can be written as it
+/// is produced. JSON output is a single array.
+struct Writer {
+    format: Format,
+    rows: usize,
+}
+
+impl Writer {
+    fn new(format: Format) -> Self {
+        Self { format, rows: 0 }
+    }
+
+    fn header(&self) -> String {
+        match self.format {
+            Format::Csv => format!("{}\r\n", CSV_COLUMNS.join(",")),
+            Format::Json => "[".to_string(),
+        }
+    }
+
+    fn row(&mut self, out: &mut String, visit: &VisitRecord) {
+        match self.format {
+            Format::Csv => {
+                let fields = [
+                    visit.timestamp.to_rfc3339(),
+                    visit.page.clone(),
+                    visit.visitor.clone(),
+                    visit.status.to_string(),
+                    visit
+                        .cache_hit
+                        .map(|hit| hit.to_string())
+                        .unwrap_or_default(),
+                    visit.referrer.clone().unwrap_or_default(),
+                    visit.browser.clone(),
+                    visit.os.clone(),
+                    visit.device.as_str().to_string(),
+                    visit.is_bot.to_string(),
+                    visit.country.clone().unwrap_or_default(),
+                    visit.region.clone().unwrap_or_default(),
+                    visit.user_agent.clone(),
+                ];
+                let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+                out.push_str(&fields.join(","));
+                out.push_str("\r\n");
+            }
+            Format::Json => {
+                out.push_str(if self.rows == 0 { "\n" } else { ",\n" });
+                out.push_str(&serde_json::to_string(visit).expect("Failed to serialize visit"));
+            }
+        }
+        self.rows += 1;
+    }
+
+    fn footer(&self) -> &'static str {
+        match self.format {
+            Format::Csv => "",
+            Format::Json if self.rows == 0 => "]\n",
+            Format::Json => "\n]\n",
+        }
+    }
+}
+
+// Quote when needed, and defuse values a spreadsheet would run as a formula
+// (referrers and user agents come straight from the client)
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
+
+/// Streams matching records from the live store, copying one batch at a
+/// time so visits keep being logged while a large export is downloading.
+pub fn stream(
+    store: Arc<VisitStore>,
+    filter: ExportFilter,
+    format: Format,
+) -> impl futures_util::Stream<Item = Result<String, Infallible>> {
+    futures_util::stream::unfold(
+        (store, filter, Writer::new(format), Some(0)),
+        |(store, filter, mut writer, cursor)| async move {
+            let cursor = cursor?;
+            let (batch, next) = store.batch(cursor, BATCH_SIZE).await;
+            let mut chunk = if cursor == 0 {
+                writer.header()
+            } else {
+                String::new()
+            };
+            for visit in batch.iter().filter(|visit| filter.matches(visit)) {
+                writer.row(&mut chunk, visit);
+            }
+            let next = if batch.len() < BATCH_SIZE {
+                chunk.push_str(writer.footer());
+                None
+            } else {
+                Some(next)
+            };
+            Some((Ok(chunk), (store, filter, writer, next)))
+        },
+    )
+}
+
+/// Same export read straight from a store file, for use while the server
+/// is stopped or on another machine. Returns the number of rows written.
+pub fn export_file(
+    path: &Path,
+    filter: &ExportFilter,
+    format: Format,
+    out: &mut impl Write,
+) -> std::io::Result<usize> {
+    let reader = std::io::BufReader::new(std::fs::File::open(path)?);
+    let mut writer = Writer::new(format);
+    out.write_all(writer.header().as_bytes())?;
+
+    let mut chunk = String::new();
+    for line in reader.lines() {
+        let line = line?;
+        let Some(visit) = visit_store::parse_line(&line) else {
+            continue;
+        };
+        if filter.matches(&visit) {
+            chunk.clear();
+            writer.row(&mut chunk, &visit);
+            out.write_all(chunk.as_bytes())?;
+        }
+    }
+
+    out.write_all(writer.footer().as_bytes())?;
+    out.flush()?;
+    Ok(writer.rows)
+}
+
+const USAGE: &str = "usage: ethan-web export-visits [--format csv|json] [--from YYYY-MM-DD] \
+[--to YYYY-MM-DD] [--page PREFIX] [--bots true|false] [--store PATH]";
+
+/// `ethan-web export-visits ...`: writes the export to stdout and returns
+/// the exit code. The store defaults to `VISIT_STORE`.
+pub fn run_cli(args: &[String], default_store: Option<&Path>) -> i32 {
+    match parse_args(args, default_store) {
+        Ok((store, filter, format)) => {
+            let stdout = std::io::stdout();
+            let mut out = std::io::BufWriter::new(stdout.lock());
+            match export_file(&store, &filter, format, &mut out) {
+                Ok(rows) => {
+                    eprintln!("Exported {} visits from {}", rows, store.display());
+                    0
+                }
+                Err(e) => {
+                    eprintln!("Failed to export {}: {}", store.display(), e);
+                    1
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            2
+        }
+    }
+}
+
+fn parse_args(
+    args: &[String],
+    default_store: Option<&Path>,
+) -> Result<(std::path::PathBuf, ExportFilter, Format), String> {
+    let mut filter = ExportFilter::default();
+    let mut format = Format::Csv;
+    let mut store = default_store.map(Path::to_path_buf);
+
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", flag));
+        let invalid = |e: &dyn std::fmt::Display| format!("invalid {}: {}", flag, e);
+        match flag.as_str() {
+            "--format" => format = value()?.parse()?,
+            "--from" => filter.from = Some(value()?.parse().map_err(|e| invalid(&e))?),
+            "--to" => filter.to = Some(value()?.parse().map_err(|e| invalid(&e))?),
+            "--page" => filter.page = Some(value()?.clone()),
+            "--bots" => filter.bots = Some(value()?.parse().map_err(|e| invalid(&e))?),
+            "--store" => store = Some(value()?.into()),
+            _ => return Err(format!("unknown argument {:?}", flag)),
+        }
+    }
+
+    let store = store.ok_or("no visit store configured; pass --store")?;
+    Ok((store, filter, format))
+}
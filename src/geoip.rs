@@ -0,0 +1,67 @@
+use maxminddb::{Reader, geoip2};
+use std::net::IpAddr;
+use std::path::Path;
+
+#[derive(Clone, Debug, Default)]
+pub struct Location {
+    /// ISO 3166-1 code, e.g. `US`
+    pub country: Option<String>,
+    /// English name of the first subdivision, e.g. `New Jersey`
+    pub region: Option<String>,
+}
+
+/// Lookups against a local MaxMind-format database. Everything is read from
+/// disk once at startup; without a database every lookup is empty.
+pub struct GeoIp {
+    reader: Option<Reader<Vec<u8>>>,
+}
+
+impl GeoIp {
+    pub fn open(path: Option<&Path>) -> Self {
+        let Some(path) = path else {
+            return Self { reader: None };
+        };
+        match Reader::open_readfile(path) {
+            Ok(reader) => {
+                tracing::info!(
+                    "Loaded GeoIP database {} ({})",
+                    path.display(),
+                    reader.metadata.database_type
+                );
+                Self {
+                    reader: Some(reader),
+                }
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "GeoIP database {} unavailable, visits won't have locations: {}",
+                    path.display(),
+                    e
+                );
+                Self { reader: None }
+            }
+        }
+    }
+
+    pub fn lookup(&self, ip: IpAddr) -> Location {
+        let Some(reader) = &self.reader else {
+            return Location::default();
+        };
+        // City records also decode from Country databases, just without subdivisions
+        let Ok(record) = reader.lookup::<geoip2::City>(ip) else {
+            return Location::default();
+        };
+
+        Location {
+            country: record
+                .country
+                .and_then(|country| country.iso_code)
+                .map(str::to_string),
+            region: record
+                .subdivisions
+                .and_then(|subdivisions| subdivisions.into_iter().next())
+                .and_then(|subdivision| subdivision.names)
+                .and_then(|names| names.get("en").map(|name| name.to_string())),
+        }
+    }
+}
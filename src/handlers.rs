@@ -1,14 +1,30 @@
+use crate::analytics::{self, VisitFilter};
+use crate::assets::read_static;
+use crate::cache::CachedPage;
+use crate::client_ip::ClientIp;
+use crate::compression::{self, Encoding};
+use crate::export::{self, ExportFilter, Format};
 use crate::models::{
-    Achievement, AchievementDetailTemplate, AchievementListItem, AchievementsTemplate, Activity,
-    ActivityDetailTemplate, ActivityListItem, ActivitiesTemplate, AppState, Award, Certification,
-    Education, HomeTemplate, PersonalInfo, Project, ProjectDetailTemplate, ProjectListItem,
-    ProjectsTemplate, Resume3Template, ResumeItem, Skill,
+    Achievement, AchievementDetailTemplate, AchievementListItem, AchievementsTemplate,
+    ActivitiesTemplate, Activity, ActivityDetailTemplate, ActivityListItem, AnalyticsTemplate,
+    AppState, Award, Certification, Education, GalleryDetailTemplate, GalleryEntry, GalleryItem,
+    GalleryTemplate, HomeTemplate, LiveTemplate, PersonalInfo, Project, ProjectDetailTemplate,
+    ProjectListItem, ProjectsTemplate, RelatedKind, ResolvedLink, Resume3Template, ResumeItem,
+    Skill, TranscriptTemplate,
 };
+use crate::page::{PageResponse, Validators};
+use crate::security::ScriptHashes;
 use axum::{
-    extract::{ConnectInfo, Path, State},
-    response::Html,
+    Extension,
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{Html, IntoResponse, Response},
 };
-use std::net::SocketAddr;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
 use std::sync::Arc;
 
 const SITE_BASE: &str = "";
@@ -21,11 +37,12 @@ fn slugify(input: &str) -> String {
         if ch.is_ascii_alphanumeric() {
             slug.push(ch.to_ascii_lowercase());
             prev_dash = false;
-        } else if matches!(ch, ' ' | '-' | '_' | '/' | ':' | '&' | ',') {
-            if !prev_dash && !slug.is_empty() {
-                slug.push('-');
-                prev_dash = true;
-            }
+        } else if matches!(ch, ' ' | '-' | '_' | '/' | ':' | '&' | ',')
+            && !prev_dash
+            && !slug.is_empty()
+        {
+            slug.push('-');
+            prev_dash = true;
         }
     }
 
@@ -369,36 +386,73 @@ fn create_resume_data() -> ResumeItem {
     }
 }
 
+/// Every attachment filename referenced by the portfolio content.
+pub fn referenced_attachments() -> Vec<String> {
+    let resume = create_resume_data();
+    let mut names: Vec<String> = resume
+        .projects
+        .iter()
+        .map(|project| project.attachment.clone())
+        .chain(resume.awards.iter().map(|award| award.attachment.clone()))
+        .filter(|name| !name.is_empty())
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+// Drop attachments that failed manifest validation so templates never link to a 404
+fn strip_missing_attachments(state: &AppState, resume: &mut ResumeItem) {
+    for project in &mut resume.projects {
+        if !project.attachment.is_empty() && !state.attachments.contains(&project.attachment) {
+            project.attachment.clear();
+        }
+    }
+    for award in &mut resume.awards {
+        if !award.attachment.is_empty() && !state.attachments.contains(&award.attachment) {
+            award.attachment.clear();
+        }
+    }
+}
+
 pub async fn home_handler(
     State(state): State<Arc<AppState>>,
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
-) -> Html<String> {
-    let ip = addr.ip().to_string();
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+) -> PageResponse {
+    let (video, window_start) = state.media.hero_at(Utc::now());
+    let video = video.cloned();
+    // Each hero video renders a different page, so cache them separately
+    let cache_path = match &video {
+        Some(video) => format!("/#{}", video.id),
+        None => "/".to_string(),
+    };
     let template = HomeTemplate {
         name: "Ethan".to_string(),
         title: "Aspiring Computer Science Student Portfolio".to_string(),
-        video_mp4: "/static/media/videos/output.mp4".to_string(),
-        video_aac: "/static/media/videos/output_aac.mp4".to_string(),
+        video,
     };
-    render_cached_page(&state, "/", &ip, &template).await
+    // A new window can bring a different video, so If-Modified-Since must miss it
+    let last_modified = state.cache_manager.last_modified().max(window_start);
+    render_cached_variant(&state, &cache_path, "/", last_modified, ip, &headers, &template).await
 }
 
 pub async fn activities_handler(
     State(state): State<Arc<AppState>>,
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
-) -> Html<String> {
-    let ip = addr.ip().to_string();
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+) -> PageResponse {
     let activities = activities_list();
     let template = ActivitiesTemplate { activities };
-    render_cached_page(&state, "/activities", &ip, &template).await
+    render_cached_page(&state, "/activities", ip, &headers, &template).await
 }
 
 pub async fn activity_detail_handler(
     Path(slug): Path<String>,
     State(state): State<Arc<AppState>>,
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
-) -> Html<String> {
-    let ip = addr.ip().to_string();
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+) -> PageResponse {
     if let Some(item) = activities_list().into_iter().find(|entry| entry.slug == slug) {
         let (hero_image_raw, hero_alt_raw) = activity_hero(&item.activity.title);
         let hero_image_abs = hero_image_raw.map(|path| format!("{}{}", SITE_BASE, path));
@@ -430,29 +484,28 @@ pub async fn activity_detail_handler(
             keywords,
         };
         let cache_path = format!("/activities/{}", slug);
-        return render_cached_page(&state, &cache_path, &ip, &template).await;
+        return render_cached_page(&state, &cache_path, ip, &headers, &template).await;
     }
 
-    Html("<h1>Activity not found</h1>".to_string())
+    Html("<h1>Activity not found</h1>".to_string()).into()
 }
 
 pub async fn achievements_handler(
     State(state): State<Arc<AppState>>,
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
-) -> Html<String> {
-    let ip = addr.ip().to_string();
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+) -> PageResponse {
     let achievements = achievements_list();
     let template = AchievementsTemplate { achievements };
-    render_cached_page(&state, "/achievements", &ip, &template).await
+    render_cached_page(&state, "/achievements", ip, &headers, &template).await
 }
 
 pub async fn achievement_detail_handler(
     Path(slug): Path<String>,
     State(state): State<Arc<AppState>>,
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
-) -> Html<String> {
-    let ip = addr.ip().to_string();
-
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+) -> PageResponse {
     if let Some(item) = achievements_list()
         .into_iter()
         .find(|entry| entry.slug == slug)
@@ -487,30 +540,30 @@ pub async fn achievement_detail_handler(
             keywords,
         };
         let cache_path = format!("/achievements/{}", slug);
-        return render_cached_page(&state, &cache_path, &ip, &template).await;
+        return render_cached_page(&state, &cache_path, ip, &headers, &template).await;
     }
 
-    Html("<h1>Achievement not found</h1>".to_string())
+    Html("<h1>Achievement not found</h1>".to_string()).into()
 }
 
 pub async fn projects_handler(
     State(state): State<Arc<AppState>>,
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
-) -> Html<String> {
-    let ip = addr.ip().to_string();
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+) -> PageResponse {
     let projects = projects_list();
     let template = ProjectsTemplate { projects };
-    render_cached_page(&state, "/projects", &ip, &template).await
+    render_cached_page(&state, "/projects", ip, &headers, &template).await
 }
 
 pub async fn project_detail_handler(
     Path(slug): Path<String>,
     State(state): State<Arc<AppState>>,
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
-) -> Html<String> {
-    let ip = addr.ip().to_string();
-
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+) -> PageResponse {
     if let Some(item) = projects_list().into_iter().find(|entry| entry.slug == slug) {
+        let attachment = state.attachments.get(&item.project.attachment).cloned();
         let (hero_image_raw, hero_alt_raw) = project_hero(&item.project.title);
         let hero_image_abs = hero_image_raw.map(|path| format!("{}{}", SITE_BASE, path));
         let hero_image_clone = hero_image_abs.clone();
@@ -531,6 +584,7 @@ pub async fn project_detail_handler(
         let page_url = format!("{}/projects/{}", SITE_BASE, slug);
         let template = ProjectDetailTemplate {
             project: item.project,
+            attachment,
             slug: slug.clone(),
             page_url,
             hero_image,
@@ -541,18 +595,19 @@ pub async fn project_detail_handler(
             keywords,
         };
         let cache_path = format!("/projects/{}", slug);
-        return render_cached_page(&state, &cache_path, &ip, &template).await;
+        return render_cached_page(&state, &cache_path, ip, &headers, &template).await;
     }
 
-    Html("<h1>Project not found</h1>".to_string())
+    Html("<h1>Project not found</h1>".to_string()).into()
 }
 
 pub async fn resume_handler(
     State(state): State<Arc<AppState>>,
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
-) -> Html<String> {
-    let ip = addr.ip().to_string();
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+) -> PageResponse {
     let mut resume = create_resume_data();
+    strip_missing_attachments(&state, &mut resume);
 
     // Keep the published entries concise for the interactive view
     for exp in &mut resume.experience {
@@ -562,40 +617,480 @@ pub async fn resume_handler(
         }
     }
 
-    let template = Resume3Template { resume };
-    render_cached_page(&state, "/resume", &ip, &template).await
+    let attachments = resume
+        .awards
+        .iter()
+        .filter_map(|award| state.attachments.get(&award.attachment))
+        .map(|info| (info.name.clone(), info.clone()))
+        .collect();
+    let template = Resume3Template {
+        resume,
+        attachments,
+    };
+    render_cached_page(&state, "/resume", ip, &headers, &template).await
+}
+
+// Looks up the titles of the pages a gallery item links to, dropping links
+// whose target no longer exists
+fn gallery_entry(state: &AppState, item: &GalleryItem) -> GalleryEntry {
+    let related = item
+        .related
+        .iter()
+        .filter_map(|link| {
+            let title = match link.kind {
+                RelatedKind::Achievement => achievements_list()
+                    .into_iter()
+                    .find(|entry| entry.slug == link.slug)
+                    .map(|entry| entry.achievement.title),
+                RelatedKind::Project => projects_list()
+                    .into_iter()
+                    .find(|entry| entry.slug == link.slug)
+                    .map(|entry| entry.project.title),
+                RelatedKind::Activity => activities_list()
+                    .into_iter()
+                    .find(|entry| entry.slug == link.slug)
+                    .map(|entry| entry.activity.title),
+            };
+            if title.is_none() {
+                tracing::warn!(
+                    "Gallery item {} links to unknown {} {}",
+                    item.slug,
+                    link.kind.label().to_lowercase(),
+                    link.slug
+                );
+            }
+            title.map(|title| ResolvedLink {
+                label: link.kind.label(),
+                title,
+                url: link.kind.url(&link.slug),
+            })
+        })
+        .collect();
+
+    GalleryEntry {
+        item: item.clone(),
+        video: item
+            .video
+            .as_deref()
+            .and_then(|id| state.media.find(id))
+            .cloned(),
+        related,
+    }
+}
+
+pub async fn gallery_handler(
+    State(state): State<Arc<AppState>>,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+) -> PageResponse {
+    let entries = state
+        .media
+        .gallery()
+        .iter()
+        .map(|item| gallery_entry(&state, item))
+        .collect();
+    let template = GalleryTemplate { entries };
+    render_cached_page(&state, "/media", ip, &headers, &template).await
+}
+
+pub async fn gallery_detail_handler(
+    Path(slug): Path<String>,
+    State(state): State<Arc<AppState>>,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+) -> PageResponse {
+    if let Some(item) = state.media.gallery().iter().find(|item| item.slug == slug) {
+        let entry = gallery_entry(&state, item);
+        let cache_path = entry.url();
+        let template = GalleryDetailTemplate {
+            page_url: format!("{}{}", SITE_BASE, cache_path),
+            entry,
+        };
+        return render_cached_page(&state, &cache_path, ip, &headers, &template).await;
+    }
+
+    Html("<h1>Media not found</h1>".to_string()).into()
+}
+
+#[derive(Deserialize)]
+pub struct CaptionQuery {
+    pub lang: Option<String>,
+}
+
+pub async fn captions_handler(
+    Path(id): Path<String>,
+    Query(query): Query<CaptionQuery>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    let Some(caption) = state
+        .media
+        .find(&id)
+        .and_then(|video| video.caption(query.lang.as_deref()))
+    else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    match read_static(&caption.file).await {
+        Ok(bytes) => (
+            [
+                (header::CONTENT_TYPE, "text/vtt; charset=utf-8"),
+                (header::CACHE_CONTROL, crate::assets::SHORT_CACHE_CONTROL),
+            ],
+            bytes.into_owned(),
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Failed to read captions {}: {}", caption.file, e);
+            StatusCode::NOT_FOUND.into_response()
+        }
+    }
+}
+
+/// Renders the cues of a caption track as a plain HTML transcript.
+pub async fn transcript_handler(
+    Path(id): Path<String>,
+    Query(query): Query<CaptionQuery>,
+    State(state): State<Arc<AppState>>,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+) -> PageResponse {
+    let Some(video) = state.media.find(&id) else {
+        return Html("<h1>Video not found</h1>".to_string()).into();
+    };
+    let Some(caption) = video.caption(query.lang.as_deref()) else {
+        return Html("<h1>Transcript not found</h1>".to_string()).into();
+    };
+
+    let cues = match read_static(&caption.file).await {
+        Ok(bytes) => crate::vtt::parse(&String::from_utf8_lossy(&bytes)).unwrap_or_default(),
+        Err(e) => {
+            tracing::error!("Failed to read captions {}: {}", caption.file, e);
+            Vec::new()
+        }
+    };
+
+    let path = video.transcript_url();
+    let template = TranscriptTemplate {
+        page_url: format!("{}{}", SITE_BASE, path),
+        video: video.clone(),
+        caption: caption.clone(),
+        cues,
+    };
+    let cache_path = format!("{}#{}", path, caption.srclang);
+    let last_modified = state.cache_manager.last_modified();
+    render_cached_variant(&state, &cache_path, &path, last_modified, ip, &headers, &template).await
+}
+
+#[derive(Deserialize)]
+pub struct AttachmentQuery {
+    #[serde(default)]
+    pub download: bool,
+}
+
+pub async fn attachment_handler(
+    Path(name): Path<String>,
+    Query(query): Query<AttachmentQuery>,
+    State(state): State<Arc<AppState>>,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+) -> Response {
+    let Some(info) = state.attachments.get(&name) else {
+        tracing::warn!("Rejected request for unknown attachment {}", name);
+        return (StatusCode::NOT_FOUND, "Attachment not found").into_response();
+    };
+
+    // Only explicit downloads count as downloads; opening a PDF in the browser is a page view
+    let path = if query.download {
+        format!("/attachments/{}?download=true", info.name)
+    } else {
+        format!("/attachments/{}", info.name)
+    };
+    let etag = format!("\"{}\"", info.sha256);
+    if crate::page::etag_matches(&headers, &etag) {
+        log_visit(&state, &path, ip, &headers, StatusCode::NOT_MODIFIED, None).await;
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response();
+    }
+
+    let bytes = match read_static(&info.name).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::error!("Failed to read attachment {}: {}", info.name, e);
+            return (StatusCode::NOT_FOUND, "Attachment not found").into_response();
+        }
+    };
+
+    let disposition = if info.is_inline() && !query.download {
+        "inline"
+    } else {
+        "attachment"
+    };
+
+    log_visit(&state, &path, ip, &headers, StatusCode::OK, None).await;
+    tracing::info!("Served attachment {}", info.name);
+
+    (
+        [
+            (header::CONTENT_TYPE, info.content_type.to_string()),
+            (header::CONTENT_LENGTH, info.size.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("{}; filename=\"{}\"", disposition, info.name),
+            ),
+            (header::ETAG, etag),
+            (header::HeaderName::from_static("x-content-sha256"), info.sha256.clone()),
+        ],
+        bytes.into_owned(),
+    )
+        .into_response()
+}
+
+/// Traffic overview for the site owner. Not cached and not logged as a visit.
+pub async fn analytics_handler(
+    State(state): State<Arc<AppState>>,
+    Query(filter): Query<VisitFilter>,
+) -> Response {
+    let summary = {
+        let visits = state.visits.records().await;
+        analytics::summarize(&visits, &filter)
+    };
+    let mut rate_limited: Vec<_> = state
+        .metrics
+        .rate_limited()
+        .filter(|(_, count)| *count > 0)
+        .map(|(group, count)| (group.as_str().to_string(), count))
+        .collect();
+    rate_limited.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let template = AnalyticsTemplate {
+        summary,
+        include_bots: filter.bots,
+        rate_limited,
+    };
+    render_uncached(&state, &template)
+}
+
+/// Page that follows `/admin/live/events` as visits come in.
+pub async fn live_handler(State(state): State<Arc<AppState>>) -> Response {
+    render_uncached(&state, &LiveTemplate {})
 }
 
-async fn log_visit(state: &Arc<AppState>, page: &str, ip: &str) {
+// Pages rendered on every request still need their script hashes in the CSP
+fn render_uncached<T: askama::Template>(state: &AppState, template: &T) -> Response {
+    let rendered = crate::metrics::render_timed(&state.metrics, template);
+    (Extension(ScriptHashes::of(&rendered)), Html(rendered)).into_response()
+}
+
+/// Visit records as a spreadsheet-friendly CSV download.
+pub async fn export_csv_handler(
+    State(state): State<Arc<AppState>>,
+    Query(filter): Query<ExportFilter>,
+) -> Response {
+    export_response(&state, filter, Format::Csv)
+}
+
+/// Visit records as a JSON array.
+pub async fn export_json_handler(
+    State(state): State<Arc<AppState>>,
+    Query(filter): Query<ExportFilter>,
+) -> Response {
+    export_response(&state, filter, Format::Json)
+}
+
+fn export_response(state: &Arc<AppState>, filter: ExportFilter, format: Format) -> Response {
+    let filename = format!(
+        "visits-{}.{}",
+        chrono::Utc::now().format("%Y-%m-%d"),
+        format.extension()
+    );
+    let rows = export::stream(state.visits.clone(), filter, format);
+    (
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        ],
+        axum::body::Body::from_stream(rows),
+    )
+        .into_response()
+}
+
+async fn log_visit(
+    state: &Arc<AppState>,
+    page: &str,
+    ip: IpAddr,
+    headers: &HeaderMap,
+    status: StatusCode,
+    cache_hit: Option<bool>,
+) {
     use crate::models::VisitRecord;
     use chrono::Utc;
 
+    if crate::privacy::opted_out(headers) {
+        tracing::debug!("Skipped visit to {}: DNT or Sec-GPC", page);
+        return;
+    }
+
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let parsed = crate::user_agent::parse(user_agent);
+    // Query strings can carry tokens or search terms, so only keep the page
+    let referrer = headers
+        .get(header::REFERER)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| {
+            value
+                .split(['?', '#'])
+                .next()
+                .unwrap_or_default()
+                .to_string()
+        })
+        .filter(|value| !value.is_empty());
+
+    // Look up the same network that gets hashed when truncation is on
+    let location = if state.config.ip_truncation {
+        state.geoip.lookup(crate::privacy::truncate(ip))
+    } else {
+        state.geoip.lookup(ip)
+    };
+
     let visit = VisitRecord {
-        ip: ip.to_string(),
+        visitor: state.visitor_hasher.visitor_id(ip),
         page: page.to_string(),
         timestamp: Utc::now(),
+        referrer,
+        user_agent: user_agent.to_string(),
+        browser: parsed.browser,
+        os: parsed.os,
+        device: parsed.device,
+        is_bot: parsed.is_bot,
+        country: location.country,
+        region: location.region,
+        status: status.as_u16(),
+        cache_hit,
     };
-    let mut visits = state.visits.lock().await;
-    visits.push(visit);
-    tracing::info!("Logged visit to {} from {}", page, ip);
+    tracing::info!(
+        "Logged visit to {} from visitor {} ({}, {}, {}{})",
+        page,
+        visit.visitor,
+        visit.browser,
+        visit.os,
+        visit.device.as_str(),
+        if visit.is_bot { ", bot" } else { "" }
+    );
+    // Only pay for the clone while someone is watching
+    if state.live_visits.receiver_count() > 0 {
+        let _ = state.live_visits.send(visit.clone());
+    }
+    state.visits.append(visit).await;
 }
 
 // Generic function to handle cached page rendering
 async fn render_cached_page<T: askama::Template + std::fmt::Debug>(
     state: &Arc<AppState>,
     path: &str,
-    ip: &str,
+    ip: IpAddr,
+    headers: &HeaderMap,
+    template: &T,
+) -> PageResponse {
+    let last_modified = state.cache_manager.last_modified();
+    render_cached_variant(state, path, path, last_modified, ip, headers, template).await
+}
+
+// Like `render_cached_page`, for pages whose cache entry is keyed separately
+// from the path recorded in the visit log, or that change more often than
+// the content does
+async fn render_cached_variant<T: askama::Template + std::fmt::Debug>(
+    state: &Arc<AppState>,
+    cache_path: &str,
+    path: &str,
+    last_modified: DateTime<Utc>,
+    ip: IpAddr,
+    headers: &HeaderMap,
+    template: &T,
+) -> PageResponse {
+    let encoding = compression::negotiate(headers);
+
+    let key = state.cache_manager.make_key(cache_path, encoding);
+    let (page, cache_hit) = match state.cache_manager.get(&key).await {
+        Some(cached) => (cached, true),
+        None => {
+            // Compress from the cached identity body when we have one
+            let (identity, hit) = cached_identity(state, cache_path, template).await;
+            let page = CachedPage {
+                body: Bytes::from(compression::compress(encoding, &identity.body)),
+                etag: encoded_etag(&identity.etag, encoding),
+                scripts: identity.scripts,
+            };
+            state.cache_manager.set(key, page.clone()).await;
+            (page, hit)
+        }
+    };
+
+    state.cache_manager.record(cache_hit);
+    crate::logging::record_cache(cache_hit);
+
+    let validators = Validators {
+        etag: page.etag,
+        last_modified,
+    };
+    if validators.is_fresh(headers) {
+        log_visit(
+            state,
+            path,
+            ip,
+            headers,
+            StatusCode::NOT_MODIFIED,
+            Some(cache_hit),
+        )
+        .await;
+        return PageResponse::NotModified { validators };
+    }
+    log_visit(state, path, ip, headers, StatusCode::OK, Some(cache_hit)).await;
+    PageResponse::Rendered {
+        body: page.body,
+        encoding,
+        validators,
+        scripts: page.scripts,
+    }
+}
+
+// The uncompressed page, and whether it came from the cache
+async fn cached_identity<T: askama::Template>(
+    state: &Arc<AppState>,
+    cache_path: &str,
     template: &T,
-) -> Html<String> {
-    let key = state.cache_manager.make_key(path);
+) -> (CachedPage, bool) {
+    let key = state.cache_manager.make_key(cache_path, Encoding::Identity);
+    if let Some(identity) = state.cache_manager.get(&key).await {
+        return (identity, true);
+    }
+
+    let rendered = crate::metrics::render_timed(&state.metrics, template);
+    let page = CachedPage {
+        etag: format!("\"{}\"", content_hash(rendered.as_bytes())),
+        scripts: ScriptHashes::of(&rendered),
+        body: Bytes::from(rendered),
+    };
+    state.cache_manager.set(key, page.clone()).await;
+    (page, false)
+}
 
-    if let Some(cached) = state.cache_manager.get(&key).await {
-        log_visit(state, path, ip).await;
-        return Html(cached);
+// Each encoding gets its own tag, e.g. `"abc123-br"`
+fn encoded_etag(etag: &str, encoding: Encoding) -> String {
+    if encoding == Encoding::Identity {
+        etag.to_string()
+    } else {
+        format!("{}-{}\"", etag.trim_end_matches('"'), encoding.as_str())
     }
+}
 
-    let rendered = template.render().expect("Failed to render template");
-    state.cache_manager.set(key, rendered.clone()).await;
-    log_visit(state, path, ip).await;
-    Html(rendered)
+fn content_hash(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .take(16)
+        .map(|b| format!("{:02x}", b))
+        .collect()
 }
@@ -0,0 +1,111 @@
+use crate::models::{AppState, HomeTemplate};
+use axum::{
+    Json,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Embedded by build.rs; `unknown` when built outside a git checkout
+pub const GIT_COMMIT: &str = env!("GIT_COMMIT");
+
+#[derive(Serialize)]
+struct BuildInfo {
+    version: &'static str,
+    commit: &'static str,
+    uptime_seconds: u64,
+}
+
+impl BuildInfo {
+    fn new(state: &AppState) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            commit: GIT_COMMIT,
+            uptime_seconds: state.started.elapsed().as_secs(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Health {
+    status: &'static str,
+    #[serde(flatten)]
+    build: BuildInfo,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    checks: BTreeMap<&'static str, String>,
+}
+
+/// Liveness: the process is up and answering requests.
+pub async fn healthz_handler(State(state): State<Arc<AppState>>) -> Response {
+    Json(Health {
+        status: "ok",
+        build: BuildInfo::new(&state),
+        checks: BTreeMap::new(),
+    })
+    .into_response()
+}
+
+/// Readiness: everything a page request depends on works. Answers 503 with
+/// the failing checks otherwise.
+pub async fn readyz_handler(State(state): State<Arc<AppState>>) -> Response {
+    let results = [
+        ("content", check_content(&state)),
+        ("static", check_static().await),
+        ("templates", check_templates(&state)),
+        ("visit_store", state.visits.check_writable().await),
+        ("audit_log", crate::audit::check()),
+    ];
+
+    let ready = results.iter().all(|(_, result)| result.is_ok());
+    let checks = results
+        .into_iter()
+        .map(|(name, result)| (name, result.unwrap_or_else(|e| e)))
+        .collect();
+    if !ready {
+        tracing::warn!("Readiness check failed: {:?}", checks);
+    }
+
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = Health {
+        status: if ready { "ok" } else { "unavailable" },
+        build: BuildInfo::new(&state),
+        checks,
+    };
+    (status, Json(body)).into_response()
+}
+
+fn check_content(state: &AppState) -> Result<String, String> {
+    let videos = state.media.videos().len();
+    let gallery = state.media.gallery().len();
+    if videos == 0 && gallery == 0 {
+        return Err("media manifest is empty".to_string());
+    }
+    Ok(format!("{} videos, {} gallery items", videos, gallery))
+}
+
+async fn check_static() -> Result<String, String> {
+    if crate::assets::static_exists("styles.css").await {
+        Ok("ok".to_string())
+    } else {
+        Err("styles.css is missing from the static files".to_string())
+    }
+}
+
+// The home page pulls in the base layout, so this covers the shared parts
+fn check_templates(state: &AppState) -> Result<String, String> {
+    let template = HomeTemplate {
+        name: "Ethan".to_string(),
+        title: "Readiness check".to_string(),
+        video: state.media.videos().first().cloned(),
+    };
+    askama::Template::render(&template)
+        .map(|_| "ok".to_string())
+        .map_err(|e| format!("home page failed to render: {}", e))
+}
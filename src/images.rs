@@ -0,0 +1,86 @@
+use crate::assets::asset_url;
+
+pub struct ImageVariant {
+    pub url: &'static str,
+    pub width: u32,
+    pub format: &'static str,
+}
+
+pub struct ResponsiveImage {
+    pub src: &'static str,
+    pub width: u32,
+    pub height: u32,
+    pub variants: &'static [ImageVariant],
+}
+
+// Generated by build.rs from static/media/images
+include!(concat!(env!("OUT_DIR"), "/image_manifest.rs"));
+
+// Hero images span the detail column, which tops out at 700px
+pub const HERO_SIZES: &str = "(max-width: 760px) 100vw, 700px";
+
+// Gallery cards: one column on phones, up to three across on desktop
+pub const GALLERY_SIZES: &str = "(max-width: 640px) 100vw, (max-width: 1100px) 50vw, 340px";
+
+pub fn lookup(src: &str) -> Option<&'static ResponsiveImage> {
+    IMAGE_MANIFEST.iter().find(|image| image.src == src)
+}
+
+impl ResponsiveImage {
+    fn srcset(&self, format: &str) -> String {
+        self.variants
+            .iter()
+            .filter(|variant| variant.format == format)
+            .map(|variant| format!("{} {}w", srcset_url(variant.url), variant.width))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    // Smallest JPEG that still covers the desktop hero width
+    fn fallback(&self) -> &'static str {
+        self.variants
+            .iter()
+            .filter(|variant| variant.format == "jpeg")
+            .find(|variant| variant.width >= 800)
+            .or_else(|| self.variants.iter().rfind(|variant| variant.format == "jpeg"))
+            .map(|variant| variant.url)
+            .unwrap_or(self.src)
+    }
+}
+
+// `srcset` entries are whitespace separated, so spaces in file names must be encoded
+fn srcset_url(url: &str) -> String {
+    asset_url(url).replace(' ', "%20")
+}
+
+/// Renders a `<picture>` with AVIF and JPEG `srcset`s and intrinsic
+/// dimensions. Falls back to a plain `<img>` for images the build did not
+/// process.
+pub fn picture(src: &str, alt: &str, sizes: &str) -> String {
+    let alt = escape_attr(alt);
+    let Some(image) = lookup(src) else {
+        return format!(r#"<img src="{}" alt="{}">"#, escape_attr(src), alt);
+    };
+
+    format!(
+        concat!(
+            r#"<picture><source type="image/avif" srcset="{avif}" sizes="{sizes}">"#,
+            r#"<img src="{fallback}" srcset="{jpeg}" sizes="{sizes}" width="{width}" height="{height}" alt="{alt}" decoding="async"></picture>"#
+        ),
+        avif = escape_attr(&image.srcset("avif")),
+        jpeg = escape_attr(&image.srcset("jpeg")),
+        fallback = escape_attr(&srcset_url(image.fallback())),
+        sizes = escape_attr(sizes),
+        width = image.width,
+        height = image.height,
+        alt = alt,
+    )
+}
+
+fn escape_attr(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
@@ -0,0 +1,46 @@
+use crate::models::{AppState, VisitRecord};
+use axum::{
+    extract::State,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures_util::StreamExt;
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+// Visits buffered per subscriber before the oldest are dropped for it
+const CHANNEL_CAPACITY: usize = 256;
+
+/// Sender that `log_visit` publishes every new visit on. Sending never
+/// waits: a subscriber that falls more than `CHANNEL_CAPACITY` visits
+/// behind skips ahead instead of holding up requests.
+pub fn channel() -> broadcast::Sender<VisitRecord> {
+    broadcast::channel(CHANNEL_CAPACITY).0
+}
+
+/// Server-sent events for the live admin page: a `visit` event with the
+/// record as JSON for each new visit, and a `lagged` event with the number
+/// of visits skipped when this client could not keep up.
+pub async fn live_events_handler(
+    State(state): State<Arc<AppState>>,
+) -> Sse<impl futures_util::Stream<Item = Result<Event, Infallible>>> {
+    tracing::info!("Live visit stream opened");
+    let receiver = state.live_visits.subscribe();
+    let events = futures_util::stream::unfold(receiver, |mut receiver| async move {
+        let event = match receiver.recv().await {
+            Ok(visit) => Event::default()
+                .event("visit")
+                .json_data(&visit)
+                .expect("Failed to serialize visit"),
+            Err(RecvError::Lagged(skipped)) => {
+                tracing::warn!("Live visit stream fell behind, skipped {}", skipped);
+                Event::default().event("lagged").data(skipped.to_string())
+            }
+            Err(RecvError::Closed) => return None,
+        };
+        Some((Ok(event), receiver))
+    });
+    // End the stream on shutdown instead of holding up the drain
+    let events = events.take_until(state.shutdown.wait());
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
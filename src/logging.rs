@@ -1,10 +1,318 @@
-use tracing_subscriber::{EnvFilter, layer::SubscriberExt, util::SubscriberInitExt};
-
-pub fn init_logging() {
-    tracing_subscriber::registry()
-        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| {
-            EnvFilter::try_new("ethan_web=debug,tower_http=debug,axum=trace").unwrap()
-        }))
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+use crate::audit::{self, AuditLayer, AuditLog};
+use crate::client_ip::ClientIp;
+use crate::config::LogConfig;
+use crate::models::AppState;
+use axum::{
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{NaiveDate, Utc};
+use std::fs::{File, OpenOptions};
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tracing::{Instrument, Level};
+use tracing_subscriber::{
+    EnvFilter, Layer, Registry, filter::Targets, fmt::writer::BoxMakeWriter, layer::SubscriberExt,
+    util::SubscriberInitExt,
+};
+
+pub const REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+// Log files are named ethan-web.<date>.log, then ethan-web.<date>.<n>.log
+const LOG_FILE_PREFIX: &str = "ethan-web.";
+const LOG_FILE_SUFFIX: &str = ".log";
+
+// Failed writes, rotations and clean-ups of the log files
+static FILE_ERRORS: AtomicU64 = AtomicU64::new(0);
+
+/// Sets up the global subscriber. `LOG_FORMAT=json` writes one JSON object
+/// per line (with the request span's fields) for log shippers; anything
+/// else is the human-readable format. Colors are only used on a terminal.
+/// With `LOG_DIR` the same lines also go to rotating files, and `audit`
+/// events are additionally written to the hash-chained audit log.
+pub fn init_logging(config: &LogConfig) {
+    let ansi = std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+    let mut warnings = Vec::new();
+
+    let mut layers: Vec<Box<dyn Layer<Registry> + Send + Sync>> = vec![fmt_layer(
+        config.json,
+        ansi,
+        BoxMakeWriter::new(std::io::stdout),
+    )];
+
+    if let Some(dir) = &config.log_dir {
+        match RollingFile::open(dir, config.log_max_size, config.log_retention_days) {
+            Ok(file) => layers.push(fmt_layer(
+                config.json,
+                false,
+                BoxMakeWriter::new(Mutex::new(file)),
+            )),
+            Err(e) => warnings.push(format!(
+                "Failed to open log directory {}, logging to stdout only: {}",
+                dir.display(),
+                e
+            )),
+        }
+    }
+
+    let mut audit_path = None;
+    if let Some(path) = &config.audit_log {
+        match AuditLog::open(path) {
+            Ok((log, warning)) => {
+                audit_path = Some(log.path().to_path_buf());
+                warnings.extend(warning);
+                layers.push(
+                    AuditLayer::new(log)
+                        .with_filter(Targets::new().with_target(audit::TARGET, Level::INFO))
+                        .boxed(),
+                );
+            }
+            Err(e) => {
+                let problem = format!("Audit log unavailable: {}", e);
+                audit::report_unavailable(problem.clone());
+                warnings.push(problem);
+            }
+        }
+    }
+
+    tracing_subscriber::registry().with(layers).init();
+
+    if let Some(path) = audit_path {
+        tracing::info!("Writing audit log to {}", path.display());
+    }
+    for warning in warnings {
+        tracing::warn!("{}", warning);
+    }
+}
+
+fn fmt_layer(
+    json: bool,
+    ansi: bool,
+    writer: BoxMakeWriter,
+) -> Box<dyn Layer<Registry> + Send + Sync> {
+    // Audit events also show up in the regular logs
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        EnvFilter::try_new("ethan_web=debug,audit=info,tower_http=debug,axum=trace").unwrap()
+    });
+    if json {
+        tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .with_writer(writer)
+            .with_filter(filter)
+            .boxed()
+    } else {
+        tracing_subscriber::fmt::layer()
+            .with_ansi(ansi)
+            .with_writer(writer)
+            .with_filter(filter)
+            .boxed()
+    }
+}
+
+/// Log file that starts over each (UTC) day and whenever it grows past
+/// `max_size`, deleting files older than `retention_days` as it goes.
+struct RollingFile {
+    dir: PathBuf,
+    max_size: u64,
+    retention_days: u32,
+    file: File,
+    day: NaiveDate,
+    index: u32,
+    size: u64,
+}
+
+impl RollingFile {
+    fn open(dir: &Path, max_size: u64, retention_days: u32) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let day = Utc::now().date_naive();
+        // Carry on with today's newest file after a restart
+        let index = latest_index(dir, day)?;
+        let file = open_append(&log_file_path(dir, day, index))?;
+        let size = file.metadata()?.len();
+        let rolling = Self {
+            dir: dir.to_path_buf(),
+            max_size,
+            retention_days,
+            file,
+            day,
+            index,
+            size,
+        };
+        rolling.prune();
+        Ok(rolling)
+    }
+
+    fn roll(&mut self, day: NaiveDate) -> std::io::Result<()> {
+        let index = if day == self.day { self.index + 1 } else { 0 };
+        self.file = open_append(&log_file_path(&self.dir, day, index))?;
+        self.day = day;
+        self.index = index;
+        self.size = 0;
+        self.prune();
+        Ok(())
+    }
+
+    fn prune(&self) {
+        if self.retention_days == 0 {
+            return;
+        }
+        let cutoff = self.day - chrono::Duration::days(self.retention_days.into());
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let expired = log_file_day(&name.to_string_lossy()).is_some_and(|day| day < cutoff);
+            if expired {
+                // Reported on stderr: logging from the writer would recurse
+                if let Err(e) = std::fs::remove_file(entry.path()) {
+                    FILE_ERRORS.fetch_add(1, Ordering::Relaxed);
+                    eprintln!("Failed to remove old log {}: {}", entry.path().display(), e);
+                }
+            }
+        }
+    }
+}
+
+impl RollingFile {
+    fn write_rolling(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let today = Utc::now().date_naive();
+        if today != self.day || (self.size > 0 && self.size + buf.len() as u64 > self.max_size) {
+            self.roll(today)?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+}
+
+impl Write for RollingFile {
+    // The fmt layer reports the error itself; this only counts it
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.write_rolling(buf).inspect_err(|_| {
+            FILE_ERRORS.fetch_add(1, Ordering::Relaxed);
+        })
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn open_append(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn log_file_path(dir: &Path, day: NaiveDate, index: u32) -> PathBuf {
+    let name = match index {
+        0 => format!("{}{}{}", LOG_FILE_PREFIX, day, LOG_FILE_SUFFIX),
+        _ => format!("{}{}.{}{}", LOG_FILE_PREFIX, day, index, LOG_FILE_SUFFIX),
+    };
+    dir.join(name)
+}
+
+fn log_file_day(name: &str) -> Option<NaiveDate> {
+    let stem = name
+        .strip_prefix(LOG_FILE_PREFIX)?
+        .strip_suffix(LOG_FILE_SUFFIX)?;
+    let date = stem.split_once('.').map_or(stem, |(date, _)| date);
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()
+}
+
+fn latest_index(dir: &Path, day: NaiveDate) -> std::io::Result<u32> {
+    let prefix = format!("{}{}.", LOG_FILE_PREFIX, day);
+    let mut latest = 0;
+    for entry in std::fs::read_dir(dir)?.flatten() {
+        let name = entry.file_name();
+        let index = name
+            .to_string_lossy()
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix(LOG_FILE_SUFFIX))
+            .and_then(|index| index.parse::<u32>().ok());
+        if let Some(index) = index {
+            latest = latest.max(index);
+        }
+    }
+    Ok(latest)
+}
+
+/// Log file writes, rotations or clean-ups that failed since startup.
+pub fn file_errors() -> u64 {
+    FILE_ERRORS.load(Ordering::Relaxed)
+}
+
+// Ids from upstream proxies are kept if they look sane, otherwise replaced
+fn request_id(request: &Request) -> String {
+    request
+        .headers()
+        .get(&REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= 128
+                && id
+                    .chars()
+                    .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '-' | '_' | '.'))
+        })
+        .map(str::to_string)
+        .unwrap_or_else(|| {
+            let mut bytes = [0u8; 16];
+            getrandom::getrandom(&mut bytes).expect("Failed to generate request id");
+            bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+        })
+}
+
+/// Runs each request inside a `request` span carrying its id, method, path
+/// and the daily visitor id (never the address, and nothing for DNT/GPC
+/// clients, whose visits aren't recorded either), then records the status, latency and page cache outcome
+/// (set by the page handlers) and logs one line when it finishes. The id is
+/// echoed back in `x-request-id`.
+pub async fn request_span(
+    State(state): State<Arc<AppState>>,
+    ClientIp(ip): ClientIp,
+    request: Request,
+    next: Next,
+) -> Response {
+    let id = request_id(&request);
+    // Log files outlive the visit retention, so they only get what visits do
+    let visitor = if crate::privacy::opted_out(request.headers()) {
+        None
+    } else {
+        Some(state.visitor_hasher.visitor_id(ip))
+    };
+    let span = tracing::info_span!(
+        "request",
+        request_id = %id,
+        method = %request.method(),
+        path = %request.uri().path(),
+        visitor = visitor,
+        status = tracing::field::Empty,
+        latency_ms = tracing::field::Empty,
+        cache = tracing::field::Empty,
+    );
+
+    let started = Instant::now();
+    let mut response = next.run(request).instrument(span.clone()).await;
+    let latency = started.elapsed();
+
+    span.record("status", response.status().as_u16());
+    span.record("latency_ms", latency.as_secs_f64() * 1000.0);
+    span.in_scope(|| tracing::info!("Finished request"));
+
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID, value);
+    }
+    response
+}
+
+/// Notes whether the current request's page came from the render cache.
+pub fn record_cache(hit: bool) {
+    tracing::Span::current().record("cache", if hit { "hit" } else { "miss" });
 }
@@ -1,35 +1,173 @@
-use axum::{Router, routing::get};
+use axum::{Router, middleware, response::Redirect, routing::get};
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::sync::Mutex as TokioMutex;
+#[cfg(not(feature = "embed-static"))]
 use tower_http::services::ServeDir;
 
+#[cfg(not(feature = "embed-static"))]
+use crate::assets::STATIC_PATH;
+use crate::attachments::AttachmentManifest;
 use crate::cache::CacheManager;
+use crate::config::{Config, LogConfig};
+use crate::geoip::GeoIp;
 use crate::handlers::*;
 use crate::logging::init_logging;
+use crate::media::MediaManifest;
+use crate::metrics::Metrics;
 use crate::models::AppState;
+use crate::privacy::VisitorHasher;
+use crate::rate_limit::RateLimiter;
+use crate::shutdown::Shutdown;
+use crate::visit_store::VisitStore;
 
+mod admin;
+mod analytics;
+mod assets;
+mod attachments;
+mod audit;
 mod cache;
+mod client_ip;
+mod compression;
+mod config;
+#[cfg(feature = "embed-static")]
+mod embedded;
+mod export;
+mod geoip;
 mod handlers;
+mod health;
+mod images;
+mod live;
 mod logging;
+mod media;
+mod metrics;
 mod models;
-
-const STATIC_PATH: &str = concat!(env!("OUT_DIR"), "/static");
+mod page;
+mod privacy;
+mod range;
+mod rate_limit;
+mod security;
+mod sessions;
+mod shutdown;
+mod tls;
+mod user_agent;
+mod video;
+mod visit_store;
+mod vtt;
 
 #[tokio::main]
 async fn main() {
+    // Offline export runs before logging so stdout only carries the data
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("export-visits") {
+        let config = Config::from_env();
+        std::process::exit(export::run_cli(&args[1..], config.visit_store.as_deref()));
+    }
+    let log_config = LogConfig::from_env();
+    if args.first().map(String::as_str) == Some("verify-audit") {
+        std::process::exit(audit::run_cli(&args[1..], log_config.audit_log.as_deref()));
+    }
+
     // Initialize logging
-    init_logging();
+    init_logging(&log_config);
+
+    let config = Config::from_env();
 
     // Initialize cache
     let cache_manager = CacheManager::new();
 
+    // Validate the attachments referenced by the content
+    let attachments = AttachmentManifest::load(&referenced_attachments()).await;
+
+    // Validate the media manifest against the static files
+    let media = MediaManifest::load().await;
+    // Content is compiled in, so this is the only time it (re)loads
+    tracing::info!(
+        target: audit::TARGET,
+        action = "content_loaded",
+        commit = health::GIT_COMMIT,
+        videos = media.videos().len(),
+        gallery = media.gallery().len(),
+        "Loaded content"
+    );
+
+    // Visitor ids are salted hashes, never raw IPs
+    let visitor_hasher = VisitorHasher::new(config.ip_truncation);
+
+    // Optional offline GeoIP database
+    let geoip = GeoIp::open(config.geoip_database.as_deref());
+
+    // Visits persist across restarts unless VISIT_STORE is empty
+    let visits = VisitStore::open(config.visit_store.clone()).await;
+
     // Initialize app state
+    let bind_addr = config.bind_addr;
     let app_state = Arc::new(AppState {
+        config: Arc::new(config),
+        media: Arc::new(media),
         cache_manager: Arc::new(cache_manager),
-        visits: Arc::new(TokioMutex::new(Vec::new())),
+        visits: Arc::new(visits),
+        attachments: Arc::new(attachments),
+        rate_limiter: Arc::new(RateLimiter::new()),
+        visitor_hasher: Arc::new(visitor_hasher),
+        geoip: Arc::new(geoip),
+        metrics: Arc::new(Metrics::new()),
+        started: std::time::Instant::now(),
+        shutdown: Arc::new(Shutdown::new()),
+        live_visits: live::channel(),
     });
 
+    tokio::spawn(privacy::purge_expired_visits(app_state.clone()));
+    tokio::spawn(visit_store::flush_periodically(app_state.visits.clone()));
+
+    // Static files come from the binary itself or from the build output directory
+    #[cfg(feature = "embed-static")]
+    let static_files = Router::new().fallback(embedded::serve_embedded);
+    #[cfg(not(feature = "embed-static"))]
+    let static_files = Router::new().fallback_service(
+        ServeDir::new(STATIC_PATH)
+            .precompressed_br()
+            .precompressed_gzip(),
+    );
+
+    // Everything under /admin requires the admin token
+    let admin_routes = Router::new()
+        .route("/analytics", get(analytics_handler))
+        .route("/analytics/export.csv", get(export_csv_handler))
+        .route("/analytics/export.json", get(export_json_handler))
+        .route("/live", get(live_handler))
+        .route("/live/events", get(live::live_events_handler))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            admin::require_admin,
+        ));
+
+    // Metrics get their own listener when one is configured, and otherwise
+    // sit behind the admin token
+    let metrics_route = Router::new().route("/metrics", get(metrics::metrics_handler));
+    let metrics_routes = match app_state.config.metrics_addr {
+        Some(metrics_addr) => {
+            let listener = tokio::net::TcpListener::bind(metrics_addr)
+                .await
+                .unwrap_or_else(|e| panic!("Failed to bind to address {}: {}", metrics_addr, e));
+            tracing::info!("Metrics on http://{}/metrics", metrics_addr);
+            let metrics_app = metrics_route.with_state(app_state.clone());
+            let shutdown = app_state.shutdown.wait();
+            tokio::spawn(async move {
+                if let Err(e) = axum::serve(listener, metrics_app)
+                    .with_graceful_shutdown(shutdown)
+                    .await
+                {
+                    tracing::error!("Metrics server error: {}", e);
+                }
+            });
+            Router::new()
+        }
+        None => metrics_route.route_layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            admin::require_admin,
+        )),
+    };
+
     // Build the router
     let app = Router::new()
         .route("/", get(home_handler))
@@ -40,20 +178,128 @@ async fn main() {
         .route("/projects", get(projects_handler))
         .route("/projects/{slug}", get(project_detail_handler))
         .route("/resume", get(resume_handler))
-        .nest_service("/static", ServeDir::new(STATIC_PATH))
-        .with_state(app_state);
+        .route("/attachments/{name}", get(attachment_handler))
+        .route("/videos/{file}", get(video::video_handler))
+        .route("/videos/{file}/poster", get(video::poster_handler))
+        .route("/media", get(gallery_handler))
+        .route("/media/{slug}", get(gallery_detail_handler))
+        // Old gallery URLs that still show up in the logs
+        .route(
+            "/media/gallery",
+            get(|| async { Redirect::permanent("/media") }),
+        )
+        .route("/media3", get(|| async { Redirect::permanent("/media") }))
+        .route("/media/{video}/captions.vtt", get(captions_handler))
+        .route("/media/{video}/transcript", get(transcript_handler))
+        .route("/healthz", get(health::healthz_handler))
+        .route("/readyz", get(health::readyz_handler))
+        .nest("/admin", admin_routes)
+        .merge(metrics_routes)
+        .nest(
+            "/static",
+            static_files
+                .layer(middleware::from_fn(assets::static_cache_control))
+                .layer(middleware::from_fn_with_state(
+                    app_state.metrics.clone(),
+                    metrics::count_static_bytes,
+                )),
+        )
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            rate_limit::rate_limit,
+        ))
+        .layer(middleware::from_fn_with_state(
+            app_state.metrics.clone(),
+            metrics::track_requests,
+        ))
+        .layer(middleware::from_fn_with_state(
+            app_state.config.clone(),
+            security::security_headers,
+        ))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            logging::request_span,
+        ))
+        .with_state(app_state.clone());
+
+    // SIGTERM/SIGINT stop every listener
+    let signal_state = app_state.clone();
+    tokio::spawn(async move {
+        shutdown::signal().await;
+        signal_state.shutdown.trigger();
+    });
 
     // Start the server
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000")
+    let listener = tokio::net::TcpListener::bind(bind_addr)
         .await
-        .expect("Failed to bind to address 0.0.0.0:3000");
-    tracing::info!("Server running on http://0.0.0.0:3000");
-    if let Err(e) = axum::serve(
-        listener,
-        app.into_make_service_with_connect_info::<SocketAddr>(),
-    )
-    .await
-    {
-        tracing::error!("Server error: {}", e);
+        .unwrap_or_else(|e| panic!("Failed to bind to address {}: {}", bind_addr, e));
+    tracing::info!(
+        "Starting version {} (commit {})",
+        env!("CARGO_PKG_VERSION"),
+        health::GIT_COMMIT
+    );
+    let drain_timeout = std::time::Duration::from_secs(app_state.config.shutdown_timeout);
+    let server = async {
+        match app_state.config.tls() {
+            Some((cert, key)) => {
+                let https_addr = app_state.config.https_addr;
+                let redirect = axum::serve(
+                    listener,
+                    tls::redirect_app(app_state.clone(), https_addr.port()),
+                )
+                .with_graceful_shutdown(app_state.shutdown.wait());
+                tracing::info!("Redirecting http://{} to HTTPS", bind_addr);
+                tokio::spawn(async move {
+                    if let Err(e) = redirect.await {
+                        tracing::error!("Redirect server error: {}", e);
+                    }
+                });
+                tls::serve_https(
+                    https_addr,
+                    cert,
+                    key,
+                    app,
+                    &app_state.shutdown,
+                    drain_timeout,
+                )
+                .await
+            }
+            None => {
+                tracing::info!("Server running on http://{}", bind_addr);
+                axum::serve(
+                    listener,
+                    app.into_make_service_with_connect_info::<SocketAddr>(),
+                )
+                .with_graceful_shutdown(app_state.shutdown.wait())
+                .await
+            }
+        }
+    };
+
+    // In-flight requests get a grace period, then are dropped
+    let stopping = app_state.shutdown.wait();
+    tokio::select! {
+        result = server => {
+            if let Err(e) = result {
+                tracing::error!("Server error: {}", e);
+            }
+        }
+        _ = async {
+            stopping.await;
+            tokio::time::sleep(drain_timeout).await;
+        } => {
+            tracing::warn!(
+                "Connections still open after {}s, closing them",
+                drain_timeout.as_secs()
+            );
+        }
+    }
+
+    // Visits are buffered, so write out whatever the last requests logged.
+    // The page cache is rebuilt on demand and is not persisted.
+    match app_state.visits.flush().await {
+        Ok(()) => tracing::info!("Flushed the visit store"),
+        Err(e) => tracing::error!("Failed to flush the visit store: {}", e),
     }
+    tracing::info!("Shutdown complete");
 }
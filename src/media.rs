@@ -0,0 +1,221 @@
+use crate::assets::{read_static, static_exists};
+use crate::models::{GalleryItem, Video, VideoSource};
+use crate::vtt;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::HashSet;
+
+// Edited by hand alongside the files in static/media
+const MANIFEST: &str = include_str!("../content/media.json");
+
+// How long each hero video stays on the home page
+const HERO_WINDOW_SECS: i64 = 15 * 60;
+
+#[derive(Deserialize)]
+struct ManifestFile {
+    videos: Vec<Video>,
+    #[serde(default)]
+    gallery: Vec<GalleryItem>,
+}
+
+/// Videos and gallery items described by `content/media.json`, checked
+/// against the static files at startup. Entries that fail validation are
+/// logged and dropped.
+pub struct MediaManifest {
+    videos: Vec<Video>,
+    gallery: Vec<GalleryItem>,
+}
+
+impl MediaManifest {
+    pub async fn load() -> Self {
+        let file: ManifestFile =
+            serde_json::from_str(MANIFEST).expect("content/media.json is not valid");
+
+        let mut videos = Vec::new();
+        let mut ids = HashSet::new();
+        for video in file.videos {
+            if !ids.insert(video.id.clone()) {
+                tracing::error!("Duplicate video id {} in media manifest", video.id);
+                continue;
+            }
+            let problems = validate(&video).await;
+            if problems.is_empty() {
+                tracing::info!(
+                    "Registered video {} ({} sources, {:.1}s)",
+                    video.id,
+                    video.sources.len(),
+                    video.duration_seconds
+                );
+                videos.push(video);
+            } else {
+                for problem in problems {
+                    tracing::error!("Skipping video {}: {}", video.id, problem);
+                }
+            }
+        }
+
+        if !videos.iter().any(|video| video.hero) {
+            tracing::warn!("No hero videos available, the home page will render without one");
+        }
+
+        let mut gallery = Vec::new();
+        let mut slugs = HashSet::new();
+        for item in file.gallery {
+            if !slugs.insert(item.slug.clone()) {
+                tracing::error!("Duplicate gallery slug {} in media manifest", item.slug);
+                continue;
+            }
+            let problems = validate_gallery_item(&item, &videos).await;
+            if problems.is_empty() {
+                gallery.push(item);
+            } else {
+                for problem in problems {
+                    tracing::error!("Skipping gallery item {}: {}", item.slug, problem);
+                }
+            }
+        }
+        tracing::info!("Registered {} gallery items", gallery.len());
+
+        Self {
+            videos,
+            gallery,
+        }
+    }
+
+    /// Rotates through the hero videos, one per time window, so the home
+    /// page stays the same (and revalidates) within a window. Returns the
+    /// video and when its window started.
+    pub fn hero_at(&self, now: DateTime<Utc>) -> (Option<&Video>, DateTime<Utc>) {
+        let window = now.timestamp().div_euclid(HERO_WINDOW_SECS);
+        let started = DateTime::from_timestamp(window * HERO_WINDOW_SECS, 0).unwrap_or(now);
+        let heroes: Vec<&Video> = self.videos.iter().filter(|video| video.hero).collect();
+        if heroes.is_empty() {
+            return (None, started);
+        }
+        let index = window.rem_euclid(heroes.len() as i64) as usize;
+        (Some(heroes[index]), started)
+    }
+
+    pub fn find(&self, id: &str) -> Option<&Video> {
+        self.videos.iter().find(|video| video.id == id)
+    }
+
+    pub fn videos(&self) -> &[Video] {
+        &self.videos
+    }
+
+    pub fn gallery(&self) -> &[GalleryItem] {
+        &self.gallery
+    }
+
+    /// Finds the video that owns a source file served under `/videos`.
+    pub fn find_source(&self, file: &str) -> Option<(&Video, &VideoSource)> {
+        self.videos.iter().find_map(|video| {
+            video
+                .sources
+                .iter()
+                .find(|source| source.file == file)
+                .map(|source| (video, source))
+        })
+    }
+}
+
+async fn validate(video: &Video) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if video.sources.is_empty() {
+        problems.push("no sources".to_string());
+    }
+    for source in &video.sources {
+        if source.file.contains('/') || source.file.starts_with('.') {
+            problems.push(format!("source {} must be a plain file name", source.file));
+        } else if !static_exists(&format!("media/videos/{}", source.file)).await {
+            problems.push(format!("source {} is missing", source.file));
+        }
+        if !source.mime_type.starts_with("video/") {
+            problems.push(format!(
+                "source {} has non-video type {}",
+                source.file, source.mime_type
+            ));
+        }
+    }
+
+    match video.poster.strip_prefix("/static/") {
+        Some(relative) if static_exists(relative).await => {}
+        _ => problems.push(format!("poster {} is missing", video.poster)),
+    }
+
+    let mut languages = HashSet::new();
+    for caption in &video.captions {
+        if !languages.insert(caption.srclang.as_str()) {
+            problems.push(format!(
+                "more than one caption track for {}",
+                caption.srclang
+            ));
+        }
+        let cues = match read_static(&caption.file).await {
+            Ok(bytes) => vtt::parse(&String::from_utf8_lossy(&bytes)),
+            Err(e) => {
+                problems.push(format!("caption file {} is missing: {}", caption.file, e));
+                continue;
+            }
+        };
+        match cues {
+            Ok(cues) if cues.is_empty() => {
+                problems.push(format!("caption file {} has no cues", caption.file))
+            }
+            Ok(cues)
+                if cues
+                    .iter()
+                    .any(|cue| cue.end > video.duration_seconds + 1.0) =>
+            {
+                problems.push(format!(
+                    "caption file {} runs past the end of the video",
+                    caption.file
+                ))
+            }
+            Ok(_) => {}
+            Err(e) => problems.push(format!("caption file {} is invalid: {}", caption.file, e)),
+        }
+    }
+
+    if video.duration_seconds <= 0.0 {
+        problems.push(format!("invalid duration {}", video.duration_seconds));
+    }
+
+    problems
+}
+
+async fn validate_gallery_item(item: &GalleryItem, videos: &[Video]) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if item.slug.is_empty()
+        || !item
+            .slug
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    {
+        problems.push(format!(
+            "slug {:?} must be lowercase letters, digits and dashes",
+            item.slug
+        ));
+    }
+    if item.alt.trim().is_empty() {
+        problems.push("missing alt text".to_string());
+    }
+
+    match (&item.image, &item.video) {
+        (Some(image), None) => match image.strip_prefix("/static/") {
+            Some(relative) if static_exists(relative).await => {}
+            _ => problems.push(format!("image {} is missing", image)),
+        },
+        (None, Some(id)) => {
+            if !videos.iter().any(|video| &video.id == id) {
+                problems.push(format!("unknown video {}", id));
+            }
+        }
+        _ => problems.push("needs exactly one of image or video".to_string()),
+    }
+
+    problems
+}
@@ -0,0 +1,315 @@
+use crate::models::AppState;
+use crate::rate_limit::RouteGroup;
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{Method, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+// Upper bounds in seconds, as in the Prometheus client defaults
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Default)]
+struct Histogram {
+    // Non-cumulative; summed up when rendered
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        if let Some(bucket) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, label: &str, value: &str) {
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(self.buckets) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{}=\"{}\",le=\"{}\"}} {}",
+                name, label, value, bound, cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{{}=\"{}\",le=\"+Inf\"}} {}",
+            name, label, value, self.count
+        );
+        let _ = writeln!(out, "{}_sum{{{}=\"{}\"}} {}", name, label, value, self.sum);
+        let _ = writeln!(
+            out,
+            "{}_count{{{}=\"{}\"}} {}",
+            name, label, value, self.count
+        );
+    }
+}
+
+/// Counters behind `/metrics`. Routes are labelled by their pattern (e.g.
+/// `/projects/{slug}`), so the number of series stays bounded.
+#[derive(Default)]
+pub struct Metrics {
+    // (route, method, status)
+    requests: Mutex<HashMap<(String, String, u16), u64>>,
+    latency: Mutex<HashMap<String, Histogram>>,
+    renders: Mutex<HashMap<&'static str, Histogram>>,
+    static_bytes: AtomicU64,
+    // Indexed like `RouteGroup::ALL`
+    rate_limited: [AtomicU64; RouteGroup::ALL.len()],
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_render(&self, template: &'static str, elapsed: Duration) {
+        self.renders
+            .lock()
+            .unwrap()
+            .entry(template)
+            .or_default()
+            .observe(elapsed);
+    }
+
+    pub fn record_rate_limited(&self, group: RouteGroup) {
+        self.rate_limited[group as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Requests rejected by the rate limiter, per route group.
+    pub fn rate_limited(&self) -> impl Iterator<Item = (RouteGroup, u64)> + '_ {
+        RouteGroup::ALL.into_iter().map(|group| {
+            (
+                group,
+                self.rate_limited[group as usize].load(Ordering::Relaxed),
+            )
+        })
+    }
+
+    fn record_request(&self, route: String, method: String, status: u16, elapsed: Duration) {
+        self.latency
+            .lock()
+            .unwrap()
+            .entry(route.clone())
+            .or_default()
+            .observe(elapsed);
+        *self
+            .requests
+            .lock()
+            .unwrap()
+            .entry((route, method, status))
+            .or_insert(0) += 1;
+    }
+}
+
+/// Renders a template, recording how long it took under the template's
+/// type name.
+pub fn render_timed<T: askama::Template>(metrics: &Metrics, template: &T) -> String {
+    let started = Instant::now();
+    let rendered = template.render().expect("Failed to render template");
+    let name = std::any::type_name::<T>()
+        .rsplit("::")
+        .next()
+        .unwrap_or_default();
+    metrics.record_render(name, started.elapsed());
+    rendered
+}
+
+/// Counts every routed request and how long it took to produce the
+/// response head. Streaming bodies (videos, exports) finish later.
+pub async fn track_requests(
+    State(metrics): State<Arc<Metrics>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    // `/static` is a nested fallback, which has no matched path of its own
+    let route = match request.extensions().get::<MatchedPath>() {
+        Some(path) => path.as_str().to_string(),
+        None if request.uri().path().starts_with("/static/") => "/static/{*path}".to_string(),
+        None => "unmatched".to_string(),
+    };
+    let method = request.method().to_string();
+
+    let started = Instant::now();
+    let response = next.run(request).await;
+    metrics.record_request(route, method, response.status().as_u16(), started.elapsed());
+    response
+}
+
+/// Adds the body size of everything served from `/static` to the byte
+/// counter.
+pub async fn count_static_bytes(
+    State(metrics): State<Arc<Metrics>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let head = request.method() == Method::HEAD;
+    let response = next.run(request).await;
+    let length = response
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if let (false, Some(length)) = (head, length) {
+        metrics.static_bytes.fetch_add(length, Ordering::Relaxed);
+    }
+    response
+}
+
+/// Everything in the Prometheus text exposition format.
+pub async fn metrics_handler(State(state): State<Arc<AppState>>) -> Response {
+    let metrics = &state.metrics;
+    let mut out = String::new();
+
+    out.push_str("# HELP ethan_web_http_requests_total Requests by route, method and status.\n");
+    out.push_str("# TYPE ethan_web_http_requests_total counter\n");
+    let mut requests: Vec<_> = metrics
+        .requests
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(key, count)| (key.clone(), *count))
+        .collect();
+    requests.sort();
+    for ((route, method, status), count) in requests {
+        let _ = writeln!(
+            out,
+            "ethan_web_http_requests_total{{route=\"{}\",method=\"{}\",status=\"{}\"}} {}",
+            escape(&route),
+            method,
+            status,
+            count
+        );
+    }
+
+    out.push_str(
+        "# HELP ethan_web_http_request_duration_seconds Time until the response head was ready.\n",
+    );
+    out.push_str("# TYPE ethan_web_http_request_duration_seconds histogram\n");
+    {
+        let latency = metrics.latency.lock().unwrap();
+        let mut routes: Vec<_> = latency.keys().collect();
+        routes.sort();
+        for route in routes {
+            latency[route].render(
+                &mut out,
+                "ethan_web_http_request_duration_seconds",
+                "route",
+                &escape(route),
+            );
+        }
+    }
+
+    out.push_str(
+        "# HELP ethan_web_template_render_duration_seconds Time spent rendering templates.\n",
+    );
+    out.push_str("# TYPE ethan_web_template_render_duration_seconds histogram\n");
+    {
+        let renders = metrics.renders.lock().unwrap();
+        let mut templates: Vec<_> = renders.keys().collect();
+        templates.sort();
+        for template in templates {
+            renders[template].render(
+                &mut out,
+                "ethan_web_template_render_duration_seconds",
+                "template",
+                template,
+            );
+        }
+    }
+
+    out.push_str(
+        "# HELP ethan_web_rate_limited_requests_total Requests rejected by the rate limiter.\n",
+    );
+    out.push_str("# TYPE ethan_web_rate_limited_requests_total counter\n");
+    for (group, count) in metrics.rate_limited() {
+        let _ = writeln!(
+            out,
+            "ethan_web_rate_limited_requests_total{{group=\"{}\"}} {}",
+            group.as_str(),
+            count
+        );
+    }
+
+    let cache = &state.cache_manager;
+    let gauges = [
+        (
+            "ethan_web_page_cache_hits_total",
+            "counter",
+            "Page requests served without rendering the template.",
+            cache.hits(),
+        ),
+        (
+            "ethan_web_page_cache_misses_total",
+            "counter",
+            "Page requests that had to render the template.",
+            cache.misses(),
+        ),
+        (
+            "ethan_web_page_cache_entries",
+            "gauge",
+            "Entries currently in the page cache.",
+            cache.entry_count(),
+        ),
+        (
+            "ethan_web_visit_store_records",
+            "gauge",
+            "Visits held in the visit store.",
+            state.visits.len().await as u64,
+        ),
+        (
+            "ethan_web_static_bytes_served_total",
+            "counter",
+            "Body bytes sent for /static requests.",
+            metrics.static_bytes.load(Ordering::Relaxed),
+        ),
+        (
+            "ethan_web_audit_write_failures_total",
+            "counter",
+            "Audit entries that could not be written.",
+            crate::audit::write_failures(),
+        ),
+        (
+            "ethan_web_log_file_errors_total",
+            "counter",
+            "Failed writes, rotations or clean-ups of the log files.",
+            crate::logging::file_errors(),
+        ),
+    ];
+    for (name, kind, help, value) in gauges {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        let _ = writeln!(out, "{} {}", name, value);
+    }
+
+    (
+        [(
+            header::CONTENT_TYPE,
+            "text/plain; version=0.0.4; charset=utf-8",
+        )],
+        out,
+    )
+        .into_response()
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -4,9 +4,24 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct VisitRecord {
-    pub ip: String,
+    /// Salted hash of the client IP, rotated daily (see `privacy`)
+    pub visitor: String,
     pub page: String,
     pub timestamp: DateTime<Utc>,
+    /// Referring URL without its query string or fragment
+    pub referrer: Option<String>,
+    pub user_agent: String,
+    pub browser: String,
+    pub os: String,
+    pub device: crate::user_agent::DeviceClass,
+    pub is_bot: bool,
+    /// From the optional GeoIP database
+    pub country: Option<String>,
+    pub region: Option<String>,
+    pub status: u16,
+    /// Whether the page came from the render cache; `None` for responses
+    /// that are never cached
+    pub cache_hit: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -109,10 +124,169 @@ pub struct ActivityListItem {
     pub slug: String,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct VideoSource {
+    pub file: String,
+    #[serde(rename = "type")]
+    pub mime_type: String,
+    #[serde(default)]
+    pub codecs: String,
+}
+
+impl VideoSource {
+    pub fn url(&self) -> String {
+        format!("/videos/{}", self.file)
+    }
+
+    // Full `type` attribute so browsers can skip sources they can't decode
+    pub fn type_attr(&self) -> String {
+        if self.codecs.is_empty() {
+            self.mime_type.clone()
+        } else {
+            format!("{}; codecs=\"{}\"", self.mime_type, self.codecs)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Caption {
+    pub file: String,
+    pub srclang: String,
+    pub label: String,
+    #[serde(default)]
+    pub default: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Video {
+    pub id: String,
+    pub title: String,
+    pub sources: Vec<VideoSource>,
+    pub poster: String,
+    #[serde(default)]
+    pub captions: Vec<Caption>,
+    pub duration_seconds: f64,
+    #[serde(default)]
+    pub hero: bool,
+}
+
+impl Video {
+    /// The track for `lang`, or the default one when no language is given.
+    pub fn caption(&self, lang: Option<&str>) -> Option<&Caption> {
+        match lang {
+            Some(lang) => self.captions.iter().find(|caption| caption.srclang == lang),
+            None => self
+                .captions
+                .iter()
+                .find(|caption| caption.default)
+                .or_else(|| self.captions.first()),
+        }
+    }
+
+    pub fn caption_url(&self, caption: &Caption) -> String {
+        format!("/media/{}/captions.vtt?lang={}", self.id, caption.srclang)
+    }
+
+    pub fn transcript_url(&self) -> String {
+        format!("/media/{}/transcript", self.id)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RelatedKind {
+    Achievement,
+    Project,
+    Activity,
+}
+
+impl RelatedKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            RelatedKind::Achievement => "Achievement",
+            RelatedKind::Project => "Project",
+            RelatedKind::Activity => "Activity",
+        }
+    }
+
+    pub fn url(&self, slug: &str) -> String {
+        match self {
+            RelatedKind::Achievement => format!("/achievements/{}", slug),
+            RelatedKind::Project => format!("/projects/{}", slug),
+            RelatedKind::Activity => format!("/activities/{}", slug),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RelatedLink {
+    pub kind: RelatedKind,
+    pub slug: String,
+}
+
+/// An image or video listed on `/media`, described in `content/media.json`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GalleryItem {
+    pub slug: String,
+    pub title: String,
+    /// `/static/...` path of a still image
+    #[serde(default)]
+    pub image: Option<String>,
+    /// Id of a video in the manifest
+    #[serde(default)]
+    pub video: Option<String>,
+    pub alt: String,
+    pub caption: String,
+    #[serde(default)]
+    pub date: String,
+    #[serde(default)]
+    pub related: Vec<RelatedLink>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ResolvedLink {
+    pub label: &'static str,
+    pub title: String,
+    pub url: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct GalleryEntry {
+    pub item: GalleryItem,
+    pub video: Option<Video>,
+    pub related: Vec<ResolvedLink>,
+}
+
+impl GalleryEntry {
+    pub fn url(&self) -> String {
+        format!("/media/{}", self.item.slug)
+    }
+
+    // Still shown in the grid: the image itself, or the video's poster
+    pub fn thumbnail(&self) -> &str {
+        match (&self.item.image, &self.video) {
+            (Some(image), _) => image,
+            (None, Some(video)) => &video.poster,
+            (None, None) => "",
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct AppState {
+    pub config: std::sync::Arc<crate::config::Config>,
+    pub media: std::sync::Arc<crate::media::MediaManifest>,
     pub cache_manager: std::sync::Arc<crate::cache::CacheManager>,
-    pub visits: std::sync::Arc<tokio::sync::Mutex<Vec<VisitRecord>>>,
+    pub visits: std::sync::Arc<crate::visit_store::VisitStore>,
+    pub attachments: std::sync::Arc<crate::attachments::AttachmentManifest>,
+    pub rate_limiter: std::sync::Arc<crate::rate_limit::RateLimiter>,
+    pub visitor_hasher: std::sync::Arc<crate::privacy::VisitorHasher>,
+    pub geoip: std::sync::Arc<crate::geoip::GeoIp>,
+    pub metrics: std::sync::Arc<crate::metrics::Metrics>,
+    pub started: std::time::Instant,
+    pub shutdown: std::sync::Arc<crate::shutdown::Shutdown>,
+    /// Every logged visit, for `/admin/live`
+    pub live_visits: tokio::sync::broadcast::Sender<VisitRecord>,
 }
 
 #[derive(Debug, Template)]
@@ -120,10 +294,43 @@ pub struct AppState {
 pub struct HomeTemplate {
     pub name: String,
     pub title: String,
-    pub video_mp4: String,
-    pub video_aac: String,
+    pub video: Option<Video>,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "transcript.html")]
+pub struct TranscriptTemplate {
+    pub video: Video,
+    pub caption: Caption,
+    pub cues: Vec<crate::vtt::Cue>,
+    pub page_url: String,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "gallery.html")]
+pub struct GalleryTemplate {
+    pub entries: Vec<GalleryEntry>,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "gallery_detail.html")]
+pub struct GalleryDetailTemplate {
+    pub entry: GalleryEntry,
+    pub page_url: String,
 }
 
+#[derive(Debug, Template)]
+#[template(path = "admin_analytics.html")]
+pub struct AnalyticsTemplate {
+    pub summary: crate::analytics::Summary,
+    pub include_bots: bool,
+    pub rate_limited: Vec<(String, u64)>,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "admin_live.html")]
+pub struct LiveTemplate {}
+
 #[derive(Debug, Template)]
 #[template(path = "achievements.html")]
 pub struct AchievementsTemplate {
@@ -146,6 +353,7 @@ pub struct ActivitiesTemplate {
 #[template(path = "achievement_detail.html")]
 pub struct AchievementDetailTemplate {
     pub achievement: Achievement,
+    #[allow(dead_code)]
     pub slug: String,
     pub page_url: String,
     pub hero_image: String,
@@ -160,6 +368,8 @@ pub struct AchievementDetailTemplate {
 #[template(path = "project_detail.html")]
 pub struct ProjectDetailTemplate {
     pub project: Project,
+    pub attachment: Option<crate::attachments::AttachmentInfo>,
+    #[allow(dead_code)]
     pub slug: String,
     pub page_url: String,
     pub hero_image: String,
@@ -174,12 +384,21 @@ pub struct ProjectDetailTemplate {
 #[template(path = "resume3.html")]
 pub struct Resume3Template {
     pub resume: ResumeItem,
+    // Metadata for the attachments the resume links to, by file name
+    pub attachments: std::collections::HashMap<String, crate::attachments::AttachmentInfo>,
+}
+
+impl Resume3Template {
+    pub fn attachment(&self, name: &str) -> Option<&crate::attachments::AttachmentInfo> {
+        self.attachments.get(name)
+    }
 }
 
 #[derive(Debug, Template)]
 #[template(path = "activity_detail.html")]
 pub struct ActivityDetailTemplate {
     pub activity: Activity,
+    #[allow(dead_code)]
     pub slug: String,
     pub page_url: String,
     pub hero_image: String,
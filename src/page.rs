@@ -0,0 +1,115 @@
+use crate::compression::Encoding;
+use crate::security::ScriptHashes;
+use axum::{
+    body::Bytes,
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{Html, IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+
+/// Validators sent with every rendered page so browsers can revalidate
+/// instead of downloading the HTML again.
+#[derive(Clone, Debug)]
+pub struct Validators {
+    pub etag: String,
+    pub last_modified: DateTime<Utc>,
+}
+
+impl Validators {
+    /// `If-None-Match` wins over `If-Modified-Since`, per RFC 9110.
+    pub fn is_fresh(&self, headers: &HeaderMap) -> bool {
+        if headers.contains_key(header::IF_NONE_MATCH) {
+            return etag_matches(headers, &self.etag);
+        }
+
+        headers
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+            .is_some_and(|since| self.last_modified.timestamp() <= since.timestamp())
+    }
+
+    fn apply(&self, headers: &mut HeaderMap) {
+        if let Ok(etag) = HeaderValue::from_str(&self.etag) {
+            headers.insert(header::ETAG, etag);
+        }
+        let last_modified = self
+            .last_modified
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string();
+        if let Ok(last_modified) = HeaderValue::from_str(&last_modified) {
+            headers.insert(header::LAST_MODIFIED, last_modified);
+        }
+        headers.insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+    }
+}
+
+/// Whether `If-None-Match` lists `etag` (or `*`).
+pub fn etag_matches(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|if_none_match| {
+            if_none_match
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag == etag)
+        })
+}
+
+pub enum PageResponse {
+    Rendered {
+        body: Bytes,
+        encoding: Encoding,
+        validators: Validators,
+        scripts: ScriptHashes,
+    },
+    NotModified {
+        validators: Validators,
+    },
+    /// Ad-hoc HTML that never goes through the page cache
+    Uncached(Html<String>),
+}
+
+impl IntoResponse for PageResponse {
+    fn into_response(self) -> Response {
+        match self {
+            PageResponse::Rendered {
+                body,
+                encoding,
+                validators,
+                scripts,
+            } => {
+                let mut response = (
+                    [(
+                        header::CONTENT_TYPE,
+                        HeaderValue::from_static("text/html; charset=utf-8"),
+                    )],
+                    body,
+                )
+                    .into_response();
+                validators.apply(response.headers_mut());
+                response.extensions_mut().insert(scripts);
+                if encoding != Encoding::Identity {
+                    response.headers_mut().insert(
+                        header::CONTENT_ENCODING,
+                        HeaderValue::from_static(encoding.as_str()),
+                    );
+                }
+                response
+            }
+            PageResponse::NotModified { validators } => {
+                let mut response = StatusCode::NOT_MODIFIED.into_response();
+                validators.apply(response.headers_mut());
+                response
+            }
+            PageResponse::Uncached(html) => html.into_response(),
+        }
+    }
+}
+
+impl From<Html<String>> for PageResponse {
+    fn from(html: Html<String>) -> Self {
+        PageResponse::Uncached(html)
+    }
+}
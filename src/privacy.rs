@@ -0,0 +1,115 @@
+use crate::models::AppState;
+use axum::http::HeaderMap;
+use chrono::{NaiveDate, Utc};
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+struct DailySalt {
+    day: NaiveDate,
+    bytes: [u8; 32],
+}
+
+impl DailySalt {
+    fn generate(day: NaiveDate) -> Self {
+        let mut bytes = [0u8; 32];
+        getrandom::getrandom(&mut bytes).expect("Failed to generate visitor salt");
+        Self { day, bytes }
+    }
+}
+
+/// Turns client IPs into visitor ids that are stable for one UTC day. The
+/// salt only lives in memory and is replaced at midnight, so ids can't be
+/// linked across days or reversed back into addresses.
+pub struct VisitorHasher {
+    salt: Mutex<DailySalt>,
+    truncate: bool,
+}
+
+impl VisitorHasher {
+    pub fn new(truncate: bool) -> Self {
+        Self {
+            salt: Mutex::new(DailySalt::generate(Utc::now().date_naive())),
+            truncate,
+        }
+    }
+
+    pub fn visitor_id(&self, ip: IpAddr) -> String {
+        let ip = if self.truncate { truncate(ip) } else { ip };
+        let today = Utc::now().date_naive();
+
+        let mut hasher = Sha256::new();
+        {
+            let mut salt = self.salt.lock().unwrap();
+            if salt.day != today {
+                *salt = DailySalt::generate(today);
+            }
+            hasher.update(salt.bytes);
+        }
+        match ip {
+            IpAddr::V4(ip) => hasher.update(ip.octets()),
+            IpAddr::V6(ip) => hasher.update(ip.octets()),
+        }
+        hasher
+            .finalize()
+            .iter()
+            .take(16)
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+/// Keeps the IPv4 /24 or IPv6 /48 network and zeroes the rest.
+pub fn truncate(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            IpAddr::from([a, b, c, 0])
+        }
+        IpAddr::V6(ip) => {
+            let mut octets = ip.octets();
+            octets[6..].fill(0);
+            IpAddr::from(octets)
+        }
+    }
+}
+
+/// `DNT: 1` or `Sec-GPC: 1` means the visit is not recorded at all.
+pub fn opted_out(headers: &HeaderMap) -> bool {
+    ["dnt", "sec-gpc"].iter().any(|name| {
+        headers
+            .get(*name)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.trim() == "1")
+    })
+}
+
+/// Drops visits older than the configured retention window, once an hour.
+pub async fn purge_expired_visits(state: Arc<AppState>) {
+    let retention_days = state.config.visit_retention_days;
+    if retention_days == 0 {
+        return;
+    }
+
+    let mut interval = tokio::time::interval(PURGE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let cutoff = Utc::now() - chrono::Duration::days(retention_days.into());
+        let purged = state.visits.purge_before(cutoff).await;
+        if purged > 0 {
+            tracing::info!(
+                target: crate::audit::TARGET,
+                action = "visits_purged",
+                purged,
+                retention_days,
+                cutoff = %cutoff.to_rfc3339(),
+                "Purged {} visits older than {} days",
+                purged,
+                retention_days
+            );
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use axum::http::{HeaderMap, header};
+use chrono::{DateTime, Utc};
+use std::ops::RangeInclusive;
+
+// More ranges than this in one request is almost certainly abuse
+const MAX_RANGES: usize = 16;
+const BOUNDARY: &str = "ethan-web-byteranges";
+
+pub enum ByteRanges {
+    /// No usable `Range` header; send the whole body
+    Full,
+    /// One or more satisfiable ranges, sorted and with no overlaps
+    Partial(Vec<RangeInclusive<u64>>),
+    /// Every range lies outside the resource, answer with 416
+    Unsatisfiable,
+}
+
+/// Parses every range of a `bytes=` `Range` header. Malformed headers and
+/// requests with too many ranges are answered with the full body;
+/// unsatisfiable ranges are dropped, and only if none remain is the whole
+/// request unsatisfiable. Overlapping and adjacent ranges are merged, so no
+/// byte is sent twice.
+pub fn parse_byte_ranges(header: Option<&str>, len: u64) -> ByteRanges {
+    let Some(specs) = header.and_then(|value| value.trim().strip_prefix("bytes=")) else {
+        return ByteRanges::Full;
+    };
+    // An empty resource has no bytes for any range to select
+    if len == 0 {
+        return ByteRanges::Unsatisfiable;
+    }
+
+    let specs: Vec<&str> = specs.split(',').map(str::trim).collect();
+    if specs.len() > MAX_RANGES {
+        return ByteRanges::Full;
+    }
+
+    let mut ranges = Vec::new();
+    for spec in specs {
+        match parse_spec(spec, len) {
+            Some(Some(range)) => ranges.push(range),
+            Some(None) => {}
+            None => return ByteRanges::Full,
+        }
+    }
+
+    if ranges.is_empty() {
+        ByteRanges::Unsatisfiable
+    } else {
+        ByteRanges::Partial(coalesce(ranges))
+    }
+}
+
+// Sorts the ranges and joins any that overlap or touch
+fn coalesce(mut ranges: Vec<RangeInclusive<u64>>) -> Vec<RangeInclusive<u64>> {
+    ranges.sort_by_key(|range| *range.start());
+    let mut merged: Vec<RangeInclusive<u64>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if *range.start() <= last.end().saturating_add(1) => {
+                *last = *last.start()..=*last.end().max(range.end());
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+// `None` for malformed specs, `Some(None)` for ones that miss the resource
+fn parse_spec(spec: &str, len: u64) -> Option<Option<RangeInclusive<u64>>> {
+    let (start, end) = spec.split_once('-')?;
+
+    let range = match (start.trim(), end.trim()) {
+        ("", "") => return None,
+        // Suffix range: the last N bytes
+        ("", suffix) => match suffix.parse::<u64>().ok()? {
+            0 => return Some(None),
+            n => len.saturating_sub(n)..=len.saturating_sub(1),
+        },
+        (start, "") => start.parse::<u64>().ok()?..=len.saturating_sub(1),
+        (start, end) => {
+            let (start, end) = (start.parse::<u64>().ok()?, end.parse::<u64>().ok()?);
+            if start > end {
+                return None;
+            }
+            start..=end.min(len.saturating_sub(1))
+        }
+    };
+
+    if len == 0 || *range.start() >= len {
+        Some(None)
+    } else {
+        Some(Some(range))
+    }
+}
+
+/// A `Range` only applies if `If-Range` (when present) still matches the
+/// current ETag or modification date exactly.
+pub fn if_range_matches(
+    headers: &HeaderMap,
+    etag: &str,
+    last_modified: Option<DateTime<Utc>>,
+) -> bool {
+    let Some(if_range) = headers
+        .get(header::IF_RANGE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return true;
+    };
+
+    if if_range.trim_start().starts_with('"') {
+        return if_range.trim() == etag;
+    }
+    match (last_modified, DateTime::parse_from_rfc2822(if_range.trim())) {
+        (Some(modified), Ok(date)) => modified.timestamp() == date.timestamp(),
+        _ => false,
+    }
+}
+
+/// `Content-Type` of a `multipart/byteranges` response.
+pub fn multipart_content_type() -> String {
+    format!("multipart/byteranges; boundary={}", BOUNDARY)
+}
+
+/// What goes before the bytes of one part of a multipart response.
+pub fn part_header(content_type: &str, range: &RangeInclusive<u64>, len: u64) -> String {
+    format!(
+        "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+        BOUNDARY,
+        content_type,
+        range.start(),
+        range.end(),
+        len
+    )
+}
+
+/// What follows the last part of a multipart response.
+pub fn closing_delimiter() -> String {
+    format!("\r\n--{}--\r\n", BOUNDARY)
+}
@@ -0,0 +1,215 @@
+use crate::client_ip::ClientIp;
+use crate::models::AppState;
+use axum::{
+    extract::{Request, State},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Buckets idle this long have refilled completely and can be forgotten
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Requests per minute with a burst allowance, written `120/30` in the
+/// environment (or just `120` for a burst equal to the per-minute rate).
+/// A rate of 0 disables limiting for the group.
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimit {
+    pub per_minute: u32,
+    pub burst: u32,
+}
+
+impl RateLimit {
+    pub const fn new(per_minute: u32, burst: u32) -> Self {
+        Self { per_minute, burst }
+    }
+
+    fn refill_per_second(&self) -> f64 {
+        self.per_minute as f64 / 60.0
+    }
+}
+
+impl FromStr for RateLimit {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.split_once('/') {
+            Some((per_minute, burst)) => {
+                Ok(Self::new(per_minute.trim().parse()?, burst.trim().parse()?))
+            }
+            None => {
+                let per_minute = value.trim().parse()?;
+                Ok(Self::new(per_minute, per_minute))
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RouteGroup {
+    Pages,
+    Static,
+    Api,
+    Admin,
+}
+
+impl RouteGroup {
+    pub const ALL: [RouteGroup; 4] = [
+        RouteGroup::Pages,
+        RouteGroup::Static,
+        RouteGroup::Api,
+        RouteGroup::Admin,
+    ];
+
+    pub fn classify(path: &str) -> Self {
+        let first = path.trim_start_matches('/').split('/').next().unwrap_or("");
+        match first {
+            "static" | "videos" | "attachments" => RouteGroup::Static,
+            "api" => RouteGroup::Api,
+            "admin" | "metrics" => RouteGroup::Admin,
+            _ => RouteGroup::Pages,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RouteGroup::Pages => "pages",
+            RouteGroup::Static => "static",
+            RouteGroup::Api => "api",
+            RouteGroup::Admin => "admin",
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    // The group's limit, so pruning judges each bucket by its own
+    capacity: f64,
+    rate: f64,
+}
+
+struct Buckets {
+    entries: HashMap<(IpAddr, RouteGroup), Bucket>,
+    pruned: Instant,
+}
+
+/// Token buckets per client IP and route group. IPv6 clients share one
+/// bucket per /64, since a single host can pick any address in its subnet.
+pub struct RateLimiter {
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self {
+            buckets: Mutex::new(Buckets {
+                entries: HashMap::new(),
+                pruned: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes a token, or returns how long until one is available.
+    pub fn check(&self, ip: IpAddr, group: RouteGroup, limit: RateLimit) -> Result<(), Duration> {
+        if limit.per_minute == 0 {
+            return Ok(());
+        }
+        let capacity = limit.burst.max(1) as f64;
+        let rate = limit.refill_per_second();
+        let now = Instant::now();
+
+        let mut buckets = self.buckets.lock().unwrap();
+        if now.duration_since(buckets.pruned) >= PRUNE_INTERVAL {
+            buckets.entries.retain(|_, bucket| {
+                let refilled =
+                    bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * bucket.rate;
+                refilled < bucket.capacity
+            });
+            buckets.pruned = now;
+        }
+
+        let bucket = buckets
+            .entries
+            .entry((client_key(ip), group))
+            .or_insert(Bucket {
+                tokens: capacity,
+                updated: now,
+                capacity,
+                rate,
+            });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        }
+    }
+}
+
+fn client_key(ip: IpAddr) -> IpAddr {
+    match ip.to_canonical() {
+        IpAddr::V6(ip) => {
+            let mut octets = ip.octets();
+            octets[8..].fill(0);
+            IpAddr::from(octets)
+        }
+        ip => ip,
+    }
+}
+
+/// Rejects clients that exceed their route group's limit with `429` and a
+/// `Retry-After`. Rejections are counted per group instead of being logged
+/// as visits.
+pub async fn rate_limit(
+    State(state): State<Arc<AppState>>,
+    ClientIp(ip): ClientIp,
+    request: Request,
+    next: Next,
+) -> Response {
+    let config = &state.config;
+    if config
+        .rate_limit_allowlist
+        .iter()
+        .any(|network| network.contains(&ip))
+    {
+        return next.run(request).await;
+    }
+
+    let group = RouteGroup::classify(request.uri().path());
+    let limit = match group {
+        RouteGroup::Pages => config.rate_limit_pages,
+        RouteGroup::Static => config.rate_limit_static,
+        RouteGroup::Api => config.rate_limit_api,
+        RouteGroup::Admin => config.rate_limit_admin,
+    };
+
+    match state.rate_limiter.check(ip, group, limit) {
+        Ok(()) => next.run(request).await,
+        Err(wait) => {
+            state.metrics.record_rate_limited(group);
+            // The request span carries the visitor id; the address stays out of the logs
+            tracing::debug!(
+                "Rate limited request to {} ({} group)",
+                request.uri().path(),
+                group.as_str()
+            );
+            let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, retry_after.to_string())],
+                "Too many requests",
+            )
+                .into_response()
+        }
+    }
+}
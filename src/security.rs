@@ -0,0 +1,121 @@
+use crate::config::Config;
+use axum::{
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+/// `script-src` sources for a page's inline scripts, as space-separated
+/// `'sha256-…'` hashes. Handlers attach it to the response as an extension;
+/// the scripts are fixed text, so the hashes can be cached with the page.
+#[derive(Clone, Debug, Default)]
+pub struct ScriptHashes(pub String);
+
+impl ScriptHashes {
+    pub fn of(html: &str) -> Self {
+        let mut sources = Vec::new();
+        let mut rest = html;
+        while let Some(start) = rest.find("<script") {
+            let Some(tag_end) = rest[start..].find('>') else {
+                break;
+            };
+            let tag = &rest[start..start + tag_end];
+            let body_start = start + tag_end + 1;
+            let Some(body_len) = rest[body_start..].find("</script>") else {
+                break;
+            };
+            // External scripts are covered by 'self'
+            if !tag.contains(" src=") {
+                let body = &rest[body_start..body_start + body_len];
+                let digest = Sha256::digest(body.as_bytes());
+                sources.push(format!(
+                    "'sha256-{}'",
+                    base64::engine::general_purpose::STANDARD.encode(digest)
+                ));
+            }
+            rest = &rest[body_start + body_len..];
+        }
+        Self(sources.join(" "))
+    }
+}
+
+fn content_security_policy(scripts: &ScriptHashes) -> String {
+    let script_src = if scripts.0.is_empty() {
+        "script-src 'self'".to_string()
+    } else {
+        format!("script-src 'self' {}", scripts.0)
+    };
+    [
+        "default-src 'self'".to_string(),
+        script_src,
+        // Templates still carry their own <style> blocks and a few style attributes
+        "style-src 'self' 'unsafe-inline'".to_string(),
+        "img-src 'self' data:".to_string(),
+        "media-src 'self'".to_string(),
+        "object-src 'none'".to_string(),
+        "frame-ancestors 'none'".to_string(),
+        "base-uri 'self'".to_string(),
+        "form-action 'self'".to_string(),
+    ]
+    .join("; ")
+}
+
+/// Adds the security headers configured in `Config` to every response, and
+/// a hash-based CSP to HTML pages.
+pub async fn security_headers(
+    State(config): State<Arc<Config>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !config.security_headers {
+        return next.run(request).await;
+    }
+
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+
+    headers.insert(
+        header::X_CONTENT_TYPE_OPTIONS,
+        HeaderValue::from_static("nosniff"),
+    );
+    if config.hsts_max_age > 0 {
+        let hsts = format!("max-age={}", config.hsts_max_age);
+        if let Ok(value) = HeaderValue::from_str(&hsts) {
+            headers.insert(header::STRICT_TRANSPORT_SECURITY, value);
+        }
+    }
+    if let Ok(value) = HeaderValue::from_str(&config.referrer_policy) {
+        headers.insert(header::REFERRER_POLICY, value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&config.permissions_policy) {
+        headers.insert(HeaderName::from_static("permissions-policy"), value);
+    }
+
+    // A 304 carries no script hashes and must not replace the policy stored
+    // with the cached page
+    let is_html = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"));
+    if is_html && response.status() != StatusCode::NOT_MODIFIED {
+        let name = if config.csp_report_only {
+            header::CONTENT_SECURITY_POLICY_REPORT_ONLY
+        } else {
+            header::CONTENT_SECURITY_POLICY
+        };
+        let scripts = response
+            .extensions()
+            .get::<ScriptHashes>()
+            .cloned()
+            .unwrap_or_default();
+        if let Ok(value) = HeaderValue::from_str(&content_security_policy(&scripts)) {
+            response.headers_mut().insert(name, value);
+        }
+    }
+
+    response
+}
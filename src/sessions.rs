@@ -0,0 +1,189 @@
+use crate::analytics::top;
+use crate::models::VisitRecord;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+
+// A visitor idle longer than this starts a new session
+const SESSION_GAP: Duration = Duration::minutes(30);
+
+// Longer paths are cut off so similar journeys group together
+const PATH_PAGES: usize = 5;
+
+/// Consecutive page views by one hashed visitor with no gap longer than
+/// `SESSION_GAP`. There are no cookies, so visitor ids (which rotate daily)
+/// are all we go on; a session crossing midnight UTC counts as two.
+#[derive(Debug)]
+pub struct Session {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub pages: Vec<String>,
+}
+
+impl Session {
+    pub fn duration(&self) -> Duration {
+        self.end - self.start
+    }
+
+    pub fn entry(&self) -> &str {
+        &self.pages[0]
+    }
+
+    pub fn exit(&self) -> &str {
+        &self.pages[self.pages.len() - 1]
+    }
+
+    // Reloads and revalidations of the same page are one step
+    fn path(&self) -> String {
+        let mut steps: Vec<&str> = self.pages.iter().map(String::as_str).collect();
+        steps.dedup();
+        let truncated = steps.len() > PATH_PAGES;
+        steps.truncate(PATH_PAGES);
+        let mut path = steps.join(" → ");
+        if truncated {
+            path.push_str(" → …");
+        }
+        path
+    }
+}
+
+/// Splits page views into sessions. Error responses are not page views.
+pub fn sessionize<'a>(visits: impl Iterator<Item = &'a VisitRecord>) -> Vec<Session> {
+    let mut by_visitor: HashMap<&str, Vec<&VisitRecord>> = HashMap::new();
+    for visit in visits.filter(|visit| visit.status < 400) {
+        by_visitor.entry(&visit.visitor).or_default().push(visit);
+    }
+
+    let mut sessions = Vec::new();
+    for (_, mut visits) in by_visitor {
+        visits.sort_by_key(|visit| visit.timestamp);
+        let mut current: Option<Session> = None;
+        for visit in visits {
+            match &mut current {
+                Some(session) if visit.timestamp - session.end <= SESSION_GAP => {
+                    session.end = visit.timestamp;
+                    session.pages.push(visit.page.clone());
+                }
+                _ => {
+                    sessions.extend(current.take());
+                    current = Some(Session {
+                        start: visit.timestamp,
+                        end: visit.timestamp,
+                        pages: vec![visit.page.clone()],
+                    });
+                }
+            }
+        }
+        sessions.extend(current);
+    }
+    sessions.sort_by_key(|session| session.start);
+    sessions
+}
+
+#[derive(Debug)]
+pub struct FunnelStep {
+    pub label: &'static str,
+    pub sessions: usize,
+    /// Share of sessions that reached the first step
+    pub percent: f64,
+}
+
+#[derive(Debug)]
+pub struct SessionReport {
+    pub sessions: usize,
+    pub average_duration: Duration,
+    /// Sessions that saw a single page
+    pub bounces: usize,
+    pub entry_pages: Vec<(String, usize)>,
+    pub exit_pages: Vec<(String, usize)>,
+    pub paths: Vec<(String, usize)>,
+    pub funnel: Vec<FunnelStep>,
+}
+
+impl SessionReport {
+    pub fn tables(&self) -> [(&'static str, &[(String, usize)]); 3] {
+        [
+            ("Entry pages", &self.entry_pages),
+            ("Exit pages", &self.exit_pages),
+            ("Paths", &self.paths),
+        ]
+    }
+
+    pub fn average_duration_label(&self) -> String {
+        let seconds = self.average_duration.num_seconds();
+        format!("{}m {:02}s", seconds / 60, seconds % 60)
+    }
+
+    pub fn bounce_percent(&self) -> String {
+        if self.sessions == 0 {
+            return "n/a".to_string();
+        }
+        format!("{:.0}%", self.bounces as f64 * 100.0 / self.sessions as f64)
+    }
+}
+
+pub fn report(sessions: &[Session]) -> SessionReport {
+    let total = sessions.len();
+    let average_duration = if total == 0 {
+        Duration::zero()
+    } else {
+        sessions.iter().map(Session::duration).sum::<Duration>() / total as i32
+    };
+
+    SessionReport {
+        sessions: total,
+        average_duration,
+        bounces: sessions
+            .iter()
+            .filter(|session| session.pages.iter().all(|page| *page == session.pages[0]))
+            .count(),
+        entry_pages: top(sessions.iter().map(|session| session.entry().to_string())),
+        exit_pages: top(sessions.iter().map(|session| session.exit().to_string())),
+        paths: top(sessions.iter().map(Session::path)),
+        funnel: funnel(sessions),
+    }
+}
+
+type PageMatcher = fn(&str) -> bool;
+
+// Home, then the resume, then any detail page, in that order within a session
+const FUNNEL: [(&str, PageMatcher); 3] = [
+    ("Home (/)", |page| page == "/"),
+    ("Resume (/resume)", |page| page == "/resume"),
+    ("Detail page", is_detail_page),
+];
+
+fn is_detail_page(page: &str) -> bool {
+    ["/projects/", "/achievements/", "/activities/", "/media/"]
+        .iter()
+        .any(|prefix| {
+            page.strip_prefix(prefix)
+                .is_some_and(|rest| !rest.is_empty())
+        })
+}
+
+fn funnel(sessions: &[Session]) -> Vec<FunnelStep> {
+    let mut reached = [0; FUNNEL.len()];
+    for session in sessions {
+        let mut step = 0;
+        for page in &session.pages {
+            if step < FUNNEL.len() && (FUNNEL[step].1)(page) {
+                reached[step] += 1;
+                step += 1;
+            }
+        }
+    }
+
+    FUNNEL
+        .iter()
+        .zip(reached)
+        .map(|((label, _), count)| FunnelStep {
+            label,
+            sessions: count,
+            percent: if reached[0] == 0 {
+                0.0
+            } else {
+                count as f64 * 100.0 / reached[0] as f64
+            },
+        })
+        .collect()
+}
@@ -0,0 +1,122 @@
+use crate::models::AppState;
+use crate::shutdown::Shutdown;
+use axum::{
+    Router,
+    http::{StatusCode, Uri, header},
+    response::{IntoResponse, Redirect, Response},
+    routing::get,
+};
+use axum_server::tls_rustls::RustlsConfig;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+// How often the certificate files are checked for renewal
+const RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Serves the app over HTTPS (HTTP/1.1 and HTTP/2 via ALPN) until shutdown,
+/// then gives open connections `drain_timeout` to finish.
+pub async fn serve_https(
+    addr: SocketAddr,
+    cert: PathBuf,
+    key: PathBuf,
+    app: Router,
+    shutdown: &Shutdown,
+    drain_timeout: Duration,
+) -> std::io::Result<()> {
+    // axum-server is built without a crypto provider, so install ring's
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    let config = RustlsConfig::from_pem_file(&cert, &key)
+        .await
+        .unwrap_or_else(|e| {
+            panic!(
+                "Failed to load TLS certificate {} and key {}: {}",
+                cert.display(),
+                key.display(),
+                e
+            )
+        });
+    tokio::spawn(reload_on_change(config.clone(), cert, key));
+
+    let handle = axum_server::Handle::new();
+    let stopping = shutdown.wait();
+    let draining = handle.clone();
+    tokio::spawn(async move {
+        stopping.await;
+        draining.graceful_shutdown(Some(drain_timeout));
+    });
+
+    tracing::info!("Serving HTTPS on https://{}", addr);
+    axum_server::bind_rustls(addr, config)
+        .handle(handle)
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .await
+}
+
+/// Plain-HTTP listener for when HTTPS is on. Health checks are answered
+/// directly so local probes keep working; everything else is redirected.
+pub fn redirect_app(state: Arc<AppState>, https_port: u16) -> Router {
+    Router::new()
+        .route("/healthz", get(crate::health::healthz_handler))
+        .route("/readyz", get(crate::health::readyz_handler))
+        .fallback(move |headers: axum::http::HeaderMap, uri: Uri| async move {
+            redirect_to_https(&headers, &uri, https_port)
+        })
+        .with_state(state)
+}
+
+fn redirect_to_https(headers: &axum::http::HeaderMap, uri: &Uri, https_port: u16) -> Response {
+    let Some(host) = headers
+        .get(header::HOST)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|host| host.parse::<axum::http::uri::Authority>().ok())
+    else {
+        return (StatusCode::BAD_REQUEST, "Missing Host header").into_response();
+    };
+
+    let authority = if https_port == 443 {
+        host.host().to_string()
+    } else {
+        format!("{}:{}", host.host(), https_port)
+    };
+    let path = uri
+        .path_and_query()
+        .map(|path| path.as_str())
+        .unwrap_or("/");
+    Redirect::permanent(&format!("https://{}{}", authority, path)).into_response()
+}
+
+// Renewals (e.g. certbot) replace the files in place; pick them up without
+// a restart. A broken pair is logged and the previous certificate kept.
+async fn reload_on_change(config: RustlsConfig, cert: PathBuf, key: PathBuf) {
+    let mut loaded = (modified(&cert).await, modified(&key).await);
+    let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        let current = (modified(&cert).await, modified(&key).await);
+        if current == loaded {
+            continue;
+        }
+        match config.reload_from_pem_file(&cert, &key).await {
+            Ok(()) => {
+                tracing::info!("Reloaded TLS certificate from {}", cert.display());
+                loaded = current;
+            }
+            Err(e) => tracing::error!(
+                "Failed to reload TLS certificate {}, keeping the old one: {}",
+                cert.display(),
+                e
+            ),
+        }
+    }
+}
+
+async fn modified(path: &Path) -> Option<SystemTime> {
+    tokio::fs::metadata(path)
+        .await
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
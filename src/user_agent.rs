@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+
+// Bundled so bot detection works without network access
+const BOT_SIGNATURES: &str = include_str!("../content/bot-signatures.txt");
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceClass {
+    Desktop,
+    Mobile,
+    Tablet,
+    Bot,
+    Unknown,
+}
+
+impl DeviceClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeviceClass::Desktop => "desktop",
+            DeviceClass::Mobile => "mobile",
+            DeviceClass::Tablet => "tablet",
+            DeviceClass::Bot => "bot",
+            DeviceClass::Unknown => "unknown",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct UserAgent {
+    pub browser: String,
+    pub os: String,
+    pub device: DeviceClass,
+    pub is_bot: bool,
+}
+
+pub fn is_bot(user_agent: &str) -> bool {
+    if user_agent.trim().is_empty() {
+        return true;
+    }
+    let user_agent = user_agent.to_ascii_lowercase();
+    BOT_SIGNATURES
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .any(|signature| user_agent.contains(signature))
+}
+
+/// Rough browser, OS and device classification. Only the families matter
+/// for analytics, so versions are dropped.
+pub fn parse(user_agent: &str) -> UserAgent {
+    let is_bot = is_bot(user_agent);
+    UserAgent {
+        browser: browser(user_agent).to_string(),
+        os: os(user_agent).to_string(),
+        device: if is_bot {
+            DeviceClass::Bot
+        } else {
+            device(user_agent)
+        },
+        is_bot,
+    }
+}
+
+// Order matters: Edge and Opera also claim to be Chrome, Chrome claims to be Safari
+fn browser(user_agent: &str) -> &'static str {
+    const BROWSERS: &[(&str, &str)] = &[
+        ("Edg", "Edge"),
+        ("OPR/", "Opera"),
+        ("SamsungBrowser/", "Samsung Internet"),
+        ("Firefox/", "Firefox"),
+        ("FxiOS/", "Firefox"),
+        ("CriOS/", "Chrome"),
+        ("Chrome/", "Chrome"),
+        ("Safari/", "Safari"),
+    ];
+    BROWSERS
+        .iter()
+        .find(|(token, _)| user_agent.contains(token))
+        .map(|(_, name)| *name)
+        .unwrap_or("Other")
+}
+
+fn os(user_agent: &str) -> &'static str {
+    const SYSTEMS: &[(&str, &str)] = &[
+        ("Windows", "Windows"),
+        ("iPhone", "iOS"),
+        ("iPad", "iPadOS"),
+        ("Android", "Android"),
+        ("CrOS", "ChromeOS"),
+        ("Mac OS X", "macOS"),
+        ("Linux", "Linux"),
+    ];
+    SYSTEMS
+        .iter()
+        .find(|(token, _)| user_agent.contains(token))
+        .map(|(_, name)| *name)
+        .unwrap_or("Other")
+}
+
+fn device(user_agent: &str) -> DeviceClass {
+    if user_agent.contains("iPad") || user_agent.contains("Tablet") {
+        DeviceClass::Tablet
+    } else if user_agent.contains("Mobile") || user_agent.contains("iPhone") {
+        DeviceClass::Mobile
+    } else if user_agent.contains("Android") {
+        // Android tablets leave out "Mobile"
+        DeviceClass::Tablet
+    } else if user_agent.contains("Windows")
+        || user_agent.contains("Macintosh")
+        || user_agent.contains("X11")
+        || user_agent.contains("CrOS")
+    {
+        DeviceClass::Desktop
+    } else {
+        DeviceClass::Unknown
+    }
+}
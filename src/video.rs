@@ -0,0 +1,244 @@
+use crate::assets::asset_url;
+use crate::models::AppState;
+use crate::range::{
+    ByteRanges, closing_delimiter, if_range_matches, multipart_content_type, parse_byte_ranges,
+    part_header,
+};
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, State},
+    http::{HeaderMap, Method, StatusCode, header},
+    response::{IntoResponse, Redirect, Response},
+};
+use chrono::{DateTime, Utc};
+use std::io::SeekFrom;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+trait VideoReader: AsyncRead + AsyncSeek + Send + Unpin {}
+impl<T: AsyncRead + AsyncSeek + Send + Unpin> VideoReader for T {}
+
+struct OpenVideo {
+    reader: Box<dyn VideoReader>,
+    len: u64,
+    etag: String,
+    last_modified: Option<DateTime<Utc>>,
+}
+
+#[cfg(feature = "embed-static")]
+async fn open(file: &str) -> std::io::Result<OpenVideo> {
+    let asset = crate::embedded::get(&format!("media/videos/{}", file))
+        .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound))?;
+    Ok(OpenVideo {
+        reader: Box::new(std::io::Cursor::new(asset.bytes)),
+        len: asset.bytes.len() as u64,
+        etag: format!("\"{}\"", asset.etag),
+        last_modified: Some(crate::assets::content_timestamp()),
+    })
+}
+
+#[cfg(not(feature = "embed-static"))]
+async fn open(file: &str) -> std::io::Result<OpenVideo> {
+    let path = std::path::Path::new(crate::assets::STATIC_PATH)
+        .join("media/videos")
+        .join(file);
+    let handle = tokio::fs::File::open(&path).await?;
+    let metadata = handle.metadata().await?;
+    let modified = metadata.modified().ok().map(DateTime::<Utc>::from);
+    let etag = format!(
+        "\"{:x}-{:x}\"",
+        metadata.len(),
+        modified.map(|time| time.timestamp()).unwrap_or_default()
+    );
+    Ok(OpenVideo {
+        reader: Box::new(handle),
+        len: metadata.len(),
+        etag,
+        last_modified: modified,
+    })
+}
+
+fn http_date(time: DateTime<Utc>) -> String {
+    time.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+enum Segment {
+    Bytes(Bytes),
+    File(RangeInclusive<u64>),
+}
+
+impl Segment {
+    fn len(&self) -> u64 {
+        match self {
+            Segment::Bytes(bytes) => bytes.len() as u64,
+            Segment::File(range) => range.end() - range.start() + 1,
+        }
+    }
+}
+
+struct Transfer {
+    reader: Box<dyn VideoReader>,
+    segments: std::vec::IntoIter<Segment>,
+    // Bytes left in the file segment being streamed
+    remaining: u64,
+    limit: u64,
+    started: Instant,
+    sent: u64,
+}
+
+impl Transfer {
+    async fn next_chunk(&mut self) -> Option<std::io::Result<Bytes>> {
+        loop {
+            if self.remaining > 0 {
+                let size = self.remaining.min(CHUNK_SIZE as u64) as usize;
+                let mut buffer = vec![0; size];
+                if let Err(e) = self.reader.read_exact(&mut buffer).await {
+                    return Some(Err(e));
+                }
+                self.remaining -= size as u64;
+                self.throttle(size as u64).await;
+                return Some(Ok(Bytes::from(buffer)));
+            }
+
+            match self.segments.next()? {
+                Segment::Bytes(bytes) => return Some(Ok(bytes)),
+                Segment::File(range) => {
+                    let start = SeekFrom::Start(*range.start());
+                    if let Err(e) = self.reader.seek(start).await {
+                        return Some(Err(e));
+                    }
+                    self.remaining = range.end() - range.start() + 1;
+                }
+            }
+        }
+    }
+
+    // Sleep just long enough to keep the average rate under the cap
+    async fn throttle(&mut self, bytes: u64) {
+        self.sent += bytes;
+        if self.limit == 0 {
+            return;
+        }
+        let target = Duration::from_secs_f64(self.sent as f64 / self.limit as f64);
+        let elapsed = self.started.elapsed();
+        if target > elapsed {
+            tokio::time::sleep(target - elapsed).await;
+        }
+    }
+}
+
+/// Streams a video source from the media manifest with `Range`/`If-Range` support (including
+/// `multipart/byteranges` for multi-range requests) and a per-response
+/// bandwidth cap.
+pub async fn video_handler(
+    Path(file): Path<String>,
+    State(state): State<Arc<AppState>>,
+    method: Method,
+    headers: HeaderMap,
+) -> Response {
+    let Some((_, entry)) = state.media.find_source(&file) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let mime_type = entry.mime_type.clone();
+    let source = match open(&file).await {
+        Ok(source) => source,
+        Err(e) => {
+            tracing::error!("Failed to open video {}: {}", file, e);
+            return StatusCode::NOT_FOUND.into_response();
+        }
+    };
+
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .filter(|_| if_range_matches(&headers, &source.etag, source.last_modified));
+    let len = source.len;
+
+    let mut response = Response::builder()
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::ETAG, &source.etag)
+        .header(header::CACHE_CONTROL, "public, max-age=86400");
+    if let Some(modified) = source.last_modified {
+        response = response.header(header::LAST_MODIFIED, http_date(modified));
+    }
+
+    let segments = match parse_byte_ranges(range, len) {
+        ByteRanges::Full => {
+            response = response
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, &mime_type);
+            if len == 0 {
+                Vec::new()
+            } else {
+                vec![Segment::File(0..=len - 1)]
+            }
+        }
+        ByteRanges::Partial(ranges) if ranges.len() == 1 => {
+            let range = ranges[0].clone();
+            response = response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_TYPE, &mime_type)
+                .header(
+                    header::CONTENT_RANGE,
+                    format!("bytes {}-{}/{}", range.start(), range.end(), len),
+                );
+            vec![Segment::File(range)]
+        }
+        ByteRanges::Partial(ranges) => {
+            response = response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_TYPE, multipart_content_type());
+            let mut segments = Vec::new();
+            for range in ranges {
+                segments.push(Segment::Bytes(Bytes::from(part_header(
+                    &mime_type, &range, len,
+                ))));
+                segments.push(Segment::File(range));
+            }
+            segments.push(Segment::Bytes(Bytes::from(closing_delimiter())));
+            segments
+        }
+        ByteRanges::Unsatisfiable => {
+            return (
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(header::CONTENT_RANGE, format!("bytes */{}", len))],
+            )
+                .into_response();
+        }
+    };
+
+    let content_length: u64 = segments.iter().map(Segment::len).sum();
+    response = response.header(header::CONTENT_LENGTH, content_length);
+    if method == Method::HEAD || len == 0 {
+        return response.body(Body::empty()).unwrap();
+    }
+
+    let transfer = Transfer {
+        reader: source.reader,
+        segments: segments.into_iter(),
+        remaining: 0,
+        limit: state.config.video_bandwidth_limit,
+        started: Instant::now(),
+        sent: 0,
+    };
+    let stream = futures_util::stream::unfold(transfer, |mut transfer| async move {
+        transfer.next_chunk().await.map(|chunk| (chunk, transfer))
+    });
+    response.body(Body::from_stream(stream)).unwrap()
+}
+
+/// Redirects to the poster still for a video, so players can reference a
+/// stable URL while the image itself stays fingerprinted.
+pub async fn poster_handler(
+    Path(file): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Response {
+    match state.media.find_source(&file) {
+        Some((video, _)) => Redirect::temporary(asset_url(&video.poster)).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
@@ -0,0 +1,205 @@
+use crate::models::VisitRecord;
+use chrono::{DateTime, Utc};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::{Mutex, MutexGuard};
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Visit records kept in memory and appended to a JSON Lines file, one
+/// record per line, so they survive restarts and can be exported offline.
+/// Without a file the store is memory-only.
+pub struct VisitStore {
+    records: Mutex<Vec<VisitRecord>>,
+    // Records purged from the front so far; only changed with `records` locked
+    purged: AtomicUsize,
+    path: Option<PathBuf>,
+    // Always locked before `records` when both are needed
+    writer: Mutex<Option<BufWriter<File>>>,
+}
+
+impl VisitStore {
+    pub async fn open(path: Option<PathBuf>) -> Self {
+        let mut records = Vec::new();
+        let mut writer = None;
+
+        if let Some(path) = &path {
+            match load(path).await {
+                Ok(loaded) => {
+                    tracing::info!("Loaded {} visits from {}", loaded.len(), path.display());
+                    records = loaded;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => tracing::error!("Failed to read visits from {}: {}", path.display(), e),
+            }
+            match open_append(path).await {
+                Ok(file) => writer = Some(BufWriter::new(file)),
+                Err(e) => tracing::error!(
+                    "Failed to open {}, visits will only be kept in memory: {}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+
+        Self {
+            records: Mutex::new(records),
+            purged: AtomicUsize::new(0),
+            path,
+            writer: Mutex::new(writer),
+        }
+    }
+
+    pub async fn append(&self, visit: VisitRecord) {
+        let line = serde_json::to_string(&visit).expect("Failed to serialize visit");
+        let mut writer = self.writer.lock().await;
+        self.records.lock().await.push(visit);
+        if let Some(file) = writer.as_mut() {
+            let written = async {
+                file.write_all(line.as_bytes()).await?;
+                file.write_all(b"\n").await
+            };
+            if let Err(e) = written.await {
+                tracing::error!("Failed to write visit to the store: {}", e);
+            }
+        }
+    }
+
+    /// All records. Hold the guard briefly; every new visit waits on it.
+    pub async fn records(&self) -> MutexGuard<'_, Vec<VisitRecord>> {
+        self.records.lock().await
+    }
+
+    /// Copies out up to `limit` records starting at `cursor`, so long reads
+    /// can release the lock between batches, and returns the cursor to
+    /// continue from. Cursors count every record stored since startup, so
+    /// a purge between batches doesn't shift them.
+    pub async fn batch(&self, cursor: usize, limit: usize) -> (Vec<VisitRecord>, usize) {
+        let records = self.records.lock().await;
+        let purged = self.purged.load(Ordering::Relaxed);
+        let start = cursor.saturating_sub(purged).min(records.len());
+        let batch: Vec<VisitRecord> = records[start..].iter().take(limit).cloned().collect();
+        let next = purged + start + batch.len();
+        (batch, next)
+    }
+
+    pub async fn len(&self) -> usize {
+        self.records.lock().await.len()
+    }
+
+    /// For readiness checks: buffered visits can still reach the file.
+    pub async fn check_writable(&self) -> Result<String, String> {
+        let Some(path) = &self.path else {
+            return Ok("memory only".to_string());
+        };
+        let mut writer = self.writer.lock().await;
+        let Some(file) = writer.as_mut() else {
+            return Err(format!("{} could not be opened", path.display()));
+        };
+        file.flush()
+            .await
+            .map_err(|e| format!("{} is not writable: {}", path.display(), e))?;
+        Ok(path.display().to_string())
+    }
+
+    pub async fn flush(&self) -> std::io::Result<()> {
+        match self.writer.lock().await.as_mut() {
+            Some(file) => file.flush().await,
+            None => Ok(()),
+        }
+    }
+
+    /// Drops records older than `cutoff` and rewrites the file without them.
+    /// Only the front of the log is removed, keeping batch cursors stable;
+    /// a record appended slightly out of order goes with the next purge.
+    pub async fn purge_before(&self, cutoff: DateTime<Utc>) -> usize {
+        let mut writer = self.writer.lock().await;
+        let (purged, remaining) = {
+            let mut records = self.records.lock().await;
+            let purged = records
+                .iter()
+                .take_while(|visit| visit.timestamp < cutoff)
+                .count();
+            if purged == 0 {
+                return 0;
+            }
+            records.drain(..purged);
+            self.purged.fetch_add(purged, Ordering::Relaxed);
+            let mut remaining = String::new();
+            for visit in records.iter() {
+                remaining
+                    .push_str(&serde_json::to_string(visit).expect("Failed to serialize visit"));
+                remaining.push('\n');
+            }
+            (purged, remaining)
+        };
+
+        if let (Some(path), Some(file)) = (&self.path, writer.as_mut()) {
+            // Anything still buffered is also in `remaining`
+            let _ = file.flush().await;
+            match rewrite(path, &remaining).await {
+                Ok(file) => *writer = Some(BufWriter::new(file)),
+                Err(e) => tracing::error!("Failed to rewrite {}: {}", path.display(), e),
+            }
+        }
+        purged
+    }
+}
+
+/// Writes buffered visits to disk every few seconds.
+pub async fn flush_periodically(store: std::sync::Arc<VisitStore>) {
+    let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(e) = store.flush().await {
+            tracing::error!("Failed to flush the visit store: {}", e);
+        }
+    }
+}
+
+/// Parses one stored line; shared with the offline export.
+pub fn parse_line(line: &str) -> Option<VisitRecord> {
+    serde_json::from_str(line).ok()
+}
+
+async fn load(path: &Path) -> std::io::Result<Vec<VisitRecord>> {
+    let contents = tokio::fs::read_to_string(path).await?;
+    let mut records = Vec::new();
+    let mut skipped = 0;
+    for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+        match parse_line(line) {
+            Some(visit) => records.push(visit),
+            None => skipped += 1,
+        }
+    }
+    // A crash mid-write leaves at most a torn last line
+    if skipped > 0 {
+        tracing::warn!("Skipped {} unreadable lines in {}", skipped, path.display());
+    }
+    Ok(records)
+}
+
+async fn open_append(path: &Path) -> std::io::Result<File> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+}
+
+// Write the new contents beside the old file and swap it in
+async fn rewrite(path: &Path, contents: &str) -> std::io::Result<File> {
+    let temp = path.with_extension("jsonl.tmp");
+    tokio::fs::write(&temp, contents).await?;
+    tokio::fs::rename(&temp, path).await?;
+    open_append(path).await
+}
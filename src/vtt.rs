@@ -0,0 +1,123 @@
+/// A single WebVTT cue with its markup stripped, ready for a transcript.
+#[derive(Clone, Debug)]
+pub struct Cue {
+    pub start: f64,
+    pub end: f64,
+    pub speaker: Option<String>,
+    pub text: String,
+}
+
+impl Cue {
+    /// `m:ss`, or `h:mm:ss` for long videos
+    pub fn timestamp(&self) -> String {
+        let total = self.start as u64;
+        let (hours, minutes, seconds) = (total / 3600, total / 60 % 60, total % 60);
+        if hours > 0 {
+            format!("{}:{:02}:{:02}", hours, minutes, seconds)
+        } else {
+            format!("{}:{:02}", minutes, seconds)
+        }
+    }
+}
+
+/// Parses the cues out of a WebVTT file. `NOTE`, `STYLE` and `REGION` blocks
+/// are skipped; cue settings after the timings are ignored.
+pub fn parse(source: &str) -> Result<Vec<Cue>, String> {
+    let source = source.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+    let mut blocks = source
+        .split("\n\n")
+        .map(str::trim)
+        .filter(|b| !b.is_empty());
+
+    let header = blocks.next().unwrap_or_default();
+    if !header.starts_with("WEBVTT") {
+        return Err("missing WEBVTT header".to_string());
+    }
+
+    let mut cues = Vec::new();
+    for block in blocks {
+        if block.starts_with("NOTE") || block.starts_with("STYLE") || block.starts_with("REGION") {
+            continue;
+        }
+
+        let mut lines = block.lines();
+        // The identifier line is optional
+        let mut timing = lines.next().unwrap_or_default();
+        if !timing.contains("-->") {
+            timing = lines.next().unwrap_or_default();
+        }
+        let (start, end) = parse_timing(timing)
+            .ok_or_else(|| format!("invalid cue timing {:?}", timing.trim()))?;
+
+        let raw = lines.collect::<Vec<_>>().join(" ");
+        cues.push(Cue {
+            start,
+            end,
+            speaker: voice(&raw),
+            text: strip_markup(&raw),
+        });
+    }
+
+    Ok(cues)
+}
+
+fn parse_timing(line: &str) -> Option<(f64, f64)> {
+    let (start, rest) = line.split_once("-->")?;
+    let end = rest.split_whitespace().next()?;
+    let (start, end) = (parse_timestamp(start.trim())?, parse_timestamp(end)?);
+    (end >= start).then_some((start, end))
+}
+
+// `hh:mm:ss.ttt` or `mm:ss.ttt`
+fn parse_timestamp(value: &str) -> Option<f64> {
+    let (clock, millis) = value.split_once('.')?;
+    let parts = clock
+        .split(':')
+        .map(|part| part.parse::<u64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let seconds = match parts.as_slice() {
+        [hours, minutes, seconds] => hours * 3600 + minutes * 60 + seconds,
+        [minutes, seconds] => minutes * 60 + seconds,
+        _ => return None,
+    };
+    if millis.len() != 3 {
+        return None;
+    }
+    Some(seconds as f64 + millis.parse::<u64>().ok()? as f64 / 1000.0)
+}
+
+// The speaker from a `<v Name>` span, if the cue has one
+fn voice(text: &str) -> Option<String> {
+    let start = text.find("<v")?;
+    let tag = &text[start + 2..start + text[start..].find('>')?];
+    let name = tag.trim_start_matches(|c: char| c == '.' || c.is_alphanumeric());
+    let name = name.trim();
+    (!name.is_empty()).then(|| decode_entities(name))
+}
+
+fn strip_markup(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => plain.push(c),
+            _ => {}
+        }
+    }
+    decode_entities(
+        plain
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .as_str(),
+    )
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
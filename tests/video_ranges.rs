@@ -0,0 +1,307 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+const VIDEO: &str = "counterspell.mp4";
+
+struct Server {
+    child: Child,
+    addr: SocketAddr,
+}
+
+impl Server {
+    fn start(bandwidth_limit: u64) -> Self {
+        // Grab a free port, then hand it to the server
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let child = Command::new(env!("CARGO_BIN_EXE_ethan-web"))
+            .env("BIND_ADDR", addr.to_string())
+            .env("VIDEO_BANDWIDTH_LIMIT", bandwidth_limit.to_string())
+            .env("RUST_LOG", "off")
+            // Keep visit records and audit entries out of the working tree's data/
+            .env("VISIT_STORE", "")
+            .env("AUDIT_LOG", "")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("failed to start server");
+
+        let deadline = Instant::now() + Duration::from_secs(20);
+        while TcpStream::connect(addr).is_err() {
+            assert!(Instant::now() < deadline, "server did not start");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        Server { child, addr }
+    }
+
+    fn get(&self, path: &str, headers: &[(&str, &str)]) -> Response {
+        let mut stream = TcpStream::connect(self.addr).unwrap();
+        let mut request = format!(
+            "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n",
+            path
+        );
+        for (name, value) in headers {
+            request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).unwrap();
+
+        let mut raw = Vec::new();
+        stream.read_to_end(&mut raw).unwrap();
+        Response::parse(raw)
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn parse(raw: Vec<u8>) -> Self {
+        let split = raw
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .expect("no header terminator");
+        let head = String::from_utf8(raw[..split].to_vec()).unwrap();
+        let mut lines = head.split("\r\n");
+        let status = lines
+            .next()
+            .unwrap()
+            .split(' ')
+            .nth(1)
+            .unwrap()
+            .parse()
+            .unwrap();
+        let headers = lines
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+            .collect();
+        Response {
+            status,
+            headers,
+            body: raw[split + 4..].to_vec(),
+        }
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn video_bytes() -> Vec<u8> {
+    std::fs::read(format!(
+        "{}/static/media/videos/{}",
+        env!("CARGO_MANIFEST_DIR"),
+        VIDEO
+    ))
+    .unwrap()
+}
+
+fn path() -> String {
+    format!("/videos/{}", VIDEO)
+}
+
+#[test]
+fn full_request_advertises_byte_ranges() {
+    let server = Server::start(0);
+    let expected = video_bytes();
+
+    let response = server.get(&path(), &[]);
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("accept-ranges"), Some("bytes"));
+    assert_eq!(response.header("content-type"), Some("video/mp4"));
+    assert_eq!(
+        response.header("content-length"),
+        Some(expected.len().to_string().as_str())
+    );
+    assert!(response.body == expected);
+}
+
+#[test]
+fn seeking_returns_partial_content() {
+    let server = Server::start(0);
+    let expected = video_bytes();
+    let len = expected.len();
+
+    let response = server.get(&path(), &[("Range", "bytes=0-1023")]);
+    assert_eq!(response.status, 206);
+    assert_eq!(
+        response.header("content-range"),
+        Some(format!("bytes 0-1023/{}", len).as_str())
+    );
+    assert!(response.body == expected[..1024]);
+
+    // Seek into the middle and read to the end
+    let response = server.get(&path(), &[("Range", "bytes=500000-")]);
+    assert_eq!(response.status, 206);
+    assert_eq!(
+        response.header("content-range"),
+        Some(format!("bytes 500000-{}/{}", len - 1, len).as_str())
+    );
+    assert!(response.body == expected[500000..]);
+
+    // Suffix ranges fetch the tail, where MP4 players often look for the moov atom
+    let response = server.get(&path(), &[("Range", "bytes=-1000")]);
+    assert_eq!(response.status, 206);
+    assert!(response.body == expected[len - 1000..]);
+
+    // End positions past the file are clamped
+    let response = server.get(
+        &path(),
+        &[("Range", &format!("bytes={}-{}", len - 10, len + 500))],
+    );
+    assert_eq!(response.status, 206);
+    assert!(response.body == expected[len - 10..]);
+}
+
+#[test]
+fn unsatisfiable_range_returns_416() {
+    let server = Server::start(0);
+    let len = video_bytes().len();
+
+    let response = server.get(&path(), &[("Range", &format!("bytes={}-", len + 1))]);
+    assert_eq!(response.status, 416);
+    assert_eq!(
+        response.header("content-range"),
+        Some(format!("bytes */{}", len).as_str())
+    );
+}
+
+#[test]
+fn multi_range_returns_multipart_byteranges() {
+    let server = Server::start(0);
+    let expected = video_bytes();
+    let len = expected.len();
+
+    let response = server.get(&path(), &[("Range", "bytes=0-99,1000-1099,-50")]);
+    assert_eq!(response.status, 206);
+    let content_type = response.header("content-type").unwrap();
+    let boundary = content_type
+        .strip_prefix("multipart/byteranges; boundary=")
+        .expect("multipart content type");
+    assert_eq!(
+        response.header("content-length"),
+        Some(response.body.len().to_string().as_str())
+    );
+
+    let expected_parts = [(0, 99), (1000, 1099), (len - 50, len - 1)];
+    let mut rest = response.body.as_slice();
+    for (start, end) in expected_parts {
+        let header = format!(
+            "\r\n--{}\r\nContent-Type: video/mp4\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+            boundary, start, end, len
+        );
+        assert!(rest.starts_with(header.as_bytes()), "bad part header");
+        rest = &rest[header.len()..];
+        assert!(rest[..end - start + 1] == expected[start..=end]);
+        rest = &rest[end - start + 1..];
+    }
+    assert_eq!(rest, format!("\r\n--{}--\r\n", boundary).as_bytes());
+}
+
+#[test]
+fn overlapping_ranges_are_merged() {
+    let server = Server::start(0);
+    let expected = video_bytes();
+    let len = expected.len();
+
+    // Asking for the whole file repeatedly must not multiply the response
+    let repeated = format!("bytes={}", vec!["0-"; 16].join(","));
+    let response = server.get(&path(), &[("Range", &repeated)]);
+    assert_eq!(response.status, 206);
+    assert_eq!(
+        response.header("content-range"),
+        Some(format!("bytes 0-{}/{}", len - 1, len).as_str())
+    );
+    assert!(response.body == expected);
+
+    // Overlapping and adjacent ranges collapse into one, out of order or not
+    let response = server.get(&path(), &[("Range", "bytes=50-149,0-99,150-199")]);
+    assert_eq!(response.status, 206);
+    assert_eq!(
+        response.header("content-range"),
+        Some(format!("bytes 0-199/{}", len).as_str())
+    );
+    assert!(response.body == expected[..200]);
+}
+
+#[test]
+fn if_range_only_honors_current_validators() {
+    let server = Server::start(0);
+    let expected = video_bytes();
+
+    let first = server.get(&path(), &[("Range", "bytes=0-9")]);
+    let etag = first.header("etag").unwrap().to_string();
+    let last_modified = first.header("last-modified").unwrap().to_string();
+
+    let response = server.get(&path(), &[("Range", "bytes=10-19"), ("If-Range", &etag)]);
+    assert_eq!(response.status, 206);
+    assert!(response.body == expected[10..20]);
+
+    let response = server.get(
+        &path(),
+        &[("Range", "bytes=10-19"), ("If-Range", &last_modified)],
+    );
+    assert_eq!(response.status, 206);
+
+    // A stale validator means the client's cached bytes are useless: send everything
+    let response = server.get(
+        &path(),
+        &[("Range", "bytes=10-19"), ("If-Range", "\"stale\"")],
+    );
+    assert_eq!(response.status, 200);
+    assert!(response.body == expected);
+
+    let response = server.get(
+        &path(),
+        &[
+            ("Range", "bytes=10-19"),
+            ("If-Range", "Mon, 01 Jan 2001 00:00:00 GMT"),
+        ],
+    );
+    assert_eq!(response.status, 200);
+}
+
+#[test]
+fn bandwidth_cap_slows_down_large_responses() {
+    let limit = 1024 * 1024;
+    let server = Server::start(limit);
+
+    let started = Instant::now();
+    let response = server.get(&path(), &[("Range", "bytes=0-524287")]);
+    assert_eq!(response.status, 206);
+    assert_eq!(response.body.len(), 524288);
+    // Half a megabyte at 1 MiB/s takes at least ~0.5s
+    assert!(started.elapsed() >= Duration::from_millis(400));
+}
+
+#[test]
+fn unknown_videos_are_not_served() {
+    let server = Server::start(0);
+    assert_eq!(server.get("/videos/output.mp4", &[]).status, 404);
+    assert_eq!(server.get("/videos/..%2Fstyles.css", &[]).status, 404);
+}
+
+#[test]
+fn poster_redirects_to_static_image() {
+    let server = Server::start(0);
+    let response = server.get(&format!("/videos/{}/poster", VIDEO), &[]);
+    assert_eq!(response.status, 307);
+    let location = response.header("location").unwrap();
+    assert!(location.starts_with("/static/media/images/counterspell."));
+}